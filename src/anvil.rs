@@ -0,0 +1,157 @@
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use nbt::Blob;
+
+const SECTOR: u64 = 4096;
+
+// Every chunk present in a region file by its coordinates inside the region
+pub type RegionChunks = Vec<((u32, u32), nbt::Result<Blob>)>;
+
+// Reads the NBT of every chunk in a region file directly, for data simple_anvil does not expose and for files
+// that share the region format without holding chunks, like poi and entities. Only a missing or truncated header
+// fails the whole file, every chunk has its own result so one bad chunk does not hide the others.
+pub fn read_chunks<P: AsRef<Path>>(path: P) -> nbt::Result<RegionChunks> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; SECTOR as usize];
+    file.read_exact(&mut header)?;
+    let mut chunks = Vec::new();
+    for i in 0..1024 {
+        let coords = ((i % 32) as u32, (i / 32) as u32);
+        match read_entry(&mut file, &header, i) {
+            Ok(Some(blob)) => chunks.push((coords, Ok(blob))),
+            Ok(None) => {}
+            Err(e) => chunks.push((coords, Err(e))),
+        }
+    }
+    Ok(chunks)
+}
+
+// Reads a single chunk by its coordinates inside the region, `None` if it has not been generated
+pub fn read_chunk<P: AsRef<Path>>(path: P, coords: (u32, u32)) -> nbt::Result<Option<Blob>> {
+    RegionFile::open(path)?.read_chunk(coords)
+}
+
+// An open region file whose chunks are read one at a time, the header is only read once
+pub struct RegionFile {
+    file: File,
+    header: [u8; SECTOR as usize],
+}
+
+impl RegionFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> nbt::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; SECTOR as usize];
+        file.read_exact(&mut header)?;
+        Ok(RegionFile { file, header })
+    }
+
+    pub fn read_chunk(&mut self, (x, z): (u32, u32)) -> nbt::Result<Option<Blob>> {
+        read_entry(&mut self.file, &self.header, (z * 32 + x) as usize)
+    }
+}
+
+fn read_entry(file: &mut File, header: &[u8], i: usize) -> nbt::Result<Option<Blob>> {
+    let entry = &header[i * 4..i * 4 + 4];
+    let offset = u32::from_be_bytes([0, entry[0], entry[1], entry[2]]) as u64;
+    if offset == 0 {
+        return Ok(None); // Chunk has not been generated
+    }
+    file.seek(SeekFrom::Start(offset * SECTOR))?;
+    let mut length = [0u8; 4];
+    file.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length == 0 {
+        return Ok(None);
+    }
+    // The header says how many sectors the chunk takes up, a corrupt length must not allocate past them
+    if length > entry[3] as usize * SECTOR as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "chunk length {} is longer than its {} sectors",
+                length, entry[3]
+            ),
+        )
+        .into());
+    }
+    let mut data = vec![0u8; length];
+    file.read_exact(&mut data)?;
+    let mut payload = Cursor::new(&data[1..]);
+    Ok(match data[0] {
+        1 => Some(Blob::from_gzip_reader(&mut payload)?),
+        2 => Some(Blob::from_zlib_reader(&mut payload)?),
+        3 => Some(Blob::from_reader(&mut payload)?),
+        // Externally stored oversized chunks and unknown compression are skipped
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use nbt::{Blob, Value};
+
+    use super::{read_chunks, RegionFile, SECTOR};
+
+    // A region file with one chunk at 0 0 stored uncompressed in sector 2, `length` overrides its stored length
+    fn region_file(name: &str, length: Option<u32>) -> PathBuf {
+        let mut blob = Blob::new();
+        blob.insert("DataVersion", Value::Int(3120)).unwrap();
+        let mut payload = vec![3u8];
+        blob.to_writer(&mut payload).unwrap();
+
+        let mut bytes = vec![0u8; 2 * SECTOR as usize];
+        bytes[0..4].copy_from_slice(&[0, 0, 2, 1]);
+        let length = length.unwrap_or(payload.len() as u32);
+        bytes.extend_from_slice(&length.to_be_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes.resize(3 * SECTOR as usize, 0);
+
+        let path =
+            std::env::temp_dir().join(format!("mc_viewer_{}_{}.mca", name, std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_stored_chunks() {
+        let path = region_file("anvil_valid", None);
+        let mut region = RegionFile::open(&path).unwrap();
+        let chunk = region.read_chunk((0, 0)).unwrap().unwrap();
+        assert!(matches!(chunk.get("DataVersion"), Some(Value::Int(3120))));
+        assert!(region.read_chunk((1, 0)).unwrap().is_none());
+        let chunks = read_chunks(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].0, (0, 0));
+        assert!(chunks[0].1.is_ok());
+    }
+
+    #[test]
+    fn rejects_lengths_past_the_chunk_sectors() {
+        let path = region_file("anvil_too_long", Some(SECTOR as u32 + 1));
+        let result = RegionFile::open(&path).unwrap().read_chunk((0, 0));
+        let chunks = read_chunks(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].1.is_err());
+    }
+
+    #[test]
+    fn truncated_headers_fail_the_file() {
+        let path =
+            std::env::temp_dir().join(format!("mc_viewer_anvil_short_{}.mca", std::process::id()));
+        fs::write(&path, [0u8; 100]).unwrap();
+        let result = read_chunks(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
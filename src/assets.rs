@@ -0,0 +1,524 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use image::{load_from_memory, DynamicImage, GenericImageView, RgbaImage};
+use serde_json::Value;
+use zip::{result::ZipResult, ZipArchive};
+
+pub const VANILLA_ASSETS: &str = "./assets";
+// Written next to the vanilla assets when they are extracted from a client jar
+const VERSION_FILE: &str = "version.txt";
+// Client jars are extracted here before they replace the vanilla assets
+const STAGING_ASSETS: &str = "./assets.extracting";
+// Only these folders of the client jar are used by the renderer
+const EXTRACTED_FOLDERS: [&str; 3] = [
+    "assets/minecraft/textures/",
+    "assets/minecraft/models/",
+    "assets/minecraft/blockstates/",
+];
+
+// The paths of every layer and the vanilla version an identity was worked out for
+type IdentityKey = (Vec<PathBuf>, Option<String>);
+
+// Identities already worked out this session, hashing every file of a directory pack is too slow to repeat for every
+// render
+static IDENTITIES: Mutex<Vec<(IdentityKey, String)>> = Mutex::new(Vec::new());
+
+// A single layer of assets, the vanilla directory stores `minecraft/...` directly while resource packs nest
+// everything under `assets/minecraft/...`
+enum AssetSource {
+    Directory(PathBuf),
+    Zip(PathBuf, Mutex<ZipArchive<File>>),
+}
+
+impl AssetSource {
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        match self {
+            AssetSource::Directory(root) => fs::read(root.join(path)).ok(),
+            AssetSource::Zip(_, archive) => {
+                let mut archive = archive.lock().unwrap_or_else(PoisonError::into_inner);
+                let mut file = archive.by_name(path).ok()?;
+                let mut bytes = Vec::new();
+                file.read_to_end(&mut bytes).ok()?;
+                Some(bytes)
+            }
+        }
+    }
+
+    fn list(&self, dir: &str) -> Vec<String> {
+        match self {
+            AssetSource::Directory(root) => match fs::read_dir(root.join(dir)) {
+                Ok(entries) => entries
+                    .filter_map(|f| f.ok())
+                    .filter_map(|f| f.file_name().to_str().map(|n| n.to_string()))
+                    .collect(),
+                Err(_) => Vec::new(),
+            },
+            AssetSource::Zip(_, archive) => {
+                let prefix = format!("{}/", dir.trim_end_matches("/"));
+                archive
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .file_names()
+                    .filter_map(|name| name.strip_prefix(prefix.as_str()))
+                    .filter(|name| !name.is_empty() && !name.contains("/"))
+                    .map(|name| name.to_string())
+                    .collect()
+            }
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            AssetSource::Directory(root) => root,
+            AssetSource::Zip(path, _) => path,
+        }
+    }
+}
+
+// The vanilla assets with any number of resource packs layered on top, the first pack has the highest priority.
+// Paths handed to `Assets` are relative to the `assets` folder of a pack, e.g. `minecraft/textures/block/stone.png`
+pub struct Assets {
+    packs: Vec<AssetSource>,
+    vanilla: AssetSource,
+    identity: String,
+    invalid_packs: Vec<String>,
+    grass_colormap: Option<RgbaImage>,
+    foliage_colormap: Option<RgbaImage>,
+    resolution: u32,
+}
+
+impl Assets {
+    pub fn new(resource_packs: &[String]) -> Self {
+        let mut packs = Vec::new();
+        let mut invalid_packs = Vec::new();
+        for pack in resource_packs {
+            match open_pack(Path::new(pack)) {
+                Some(source) => packs.push(source),
+                None => invalid_packs.push(format!(
+                    "Skipped {}, it is not a folder or zip with a pack.mcmeta",
+                    pack
+                )),
+            }
+        }
+        let vanilla = AssetSource::Directory(PathBuf::from(VANILLA_ASSETS));
+        let identity = cached_identity(&packs, &vanilla);
+        let mut assets = Assets {
+            packs,
+            vanilla,
+            identity,
+            invalid_packs,
+            grass_colormap: None,
+            foliage_colormap: None,
+            resolution: 16,
+        };
+        assets.grass_colormap = assets.colormap_override("grass");
+        assets.foliage_colormap = assets.colormap_override("foliage");
+        // Packs are not required to use one resolution for everything, stone is as good a reference as any
+        if let Some(stone) = assets.block_texture("stone") {
+            assets.resolution = stone.width().max(1);
+        }
+        assets
+    }
+
+    fn colormap_override(&self, name: &str) -> Option<RgbaImage> {
+        let bytes = self.read_override(&format!("minecraft/textures/colormap/{}.png", name))?;
+        Some(load_from_memory(&bytes).ok()?.into_rgba8())
+    }
+
+    // Colormap provided by a resource pack, `None` when only the vanilla colormap is available
+    pub fn colormap(&self, name: &str) -> Option<&RgbaImage> {
+        match name {
+            "grass" => self.grass_colormap.as_ref(),
+            "foliage" => self.foliage_colormap.as_ref(),
+            _ => None,
+        }
+    }
+
+    // A short hash of every layer in the stack, tiles rendered with a different stack are stale
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    // Packs from the list that could not be opened and were left out of the stack
+    pub fn invalid_packs(&self) -> &[String] {
+        &self.invalid_packs
+    }
+
+    pub fn read(&self, path: &str) -> Option<Vec<u8>> {
+        self.packs
+            .iter()
+            .find_map(|source| source.read(&format!("assets/{}", path)))
+            .or_else(|| self.vanilla.read(path))
+    }
+
+    // Same as `read` but ignores the vanilla assets, used where the renderer has its own vanilla behaviour
+    fn read_override(&self, path: &str) -> Option<Vec<u8>> {
+        self.packs
+            .iter()
+            .find_map(|source| source.read(&format!("assets/{}", path)))
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.read(path).is_some()
+    }
+
+    pub fn image(&self, path: &str) -> Option<DynamicImage> {
+        load_from_memory(&self.read(path)?).ok()
+    }
+
+    pub fn json(&self, path: &str) -> Option<Value> {
+        serde_json::from_slice(&self.read(path)?).ok()
+    }
+
+    // File names in a directory across every layer
+    pub fn list(&self, dir: &str) -> Vec<String> {
+        let mut names = self.vanilla.list(dir);
+        for source in &self.packs {
+            names.append(&mut source.list(&format!("assets/{}", dir)));
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    // Width in pixels of a single block texture
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    // Loads a block texture, animated textures are reduced to the frame the game shows first
+    pub fn block_texture(&self, name: &str) -> Option<DynamicImage> {
+        let path = format!("minecraft/textures/block/{}.png", name);
+        let img = self.image(&path)?;
+        Some(animation_frame(img, self.json(&format!("{}.mcmeta", path))))
+    }
+
+    pub fn block_texture_exists(&self, name: &str) -> bool {
+        self.exists(&format!("minecraft/textures/block/{}.png", name))
+    }
+
+    // Finds the texture a block model shows from above by walking the model's parents, `None` if no layer has a
+    // model for the block
+    pub fn model_top_texture(&self, block_id: &str) -> Option<String> {
+        let mut textures = serde_json::Map::new();
+        let mut model = format!("minecraft:block/{}", block_id);
+        // Parent chains in vanilla are only a few models deep
+        for _ in 0..8 {
+            let (namespace, name) = model
+                .split_once(":")
+                .unwrap_or(("minecraft", model.as_str()));
+            let json = self.json(&format!("{}/models/{}.json", namespace, name))?;
+            if let Some(Value::Object(model_textures)) = json.get("textures") {
+                for (key, value) in model_textures {
+                    textures.entry(key.clone()).or_insert(value.clone());
+                }
+            }
+            match json.get("parent").and_then(|p| p.as_str()) {
+                Some(parent) => model = parent.to_string(),
+                None => break,
+            }
+        }
+
+        for key in ["top", "end", "all", "texture", "cross", "particle"] {
+            let mut texture = match textures.get(key).and_then(|t| t.as_str()) {
+                Some(t) => t.to_string(),
+                None => continue,
+            };
+            // Texture variables such as `#side` point at another entry in the same map
+            for _ in 0..8 {
+                match texture.strip_prefix("#") {
+                    Some(variable) => match textures.get(variable).and_then(|t| t.as_str()) {
+                        Some(t) => texture = t.to_string(),
+                        None => break,
+                    },
+                    None => break,
+                }
+            }
+            if !texture.starts_with("#") {
+                let name = texture.trim_start_matches("minecraft:");
+                return Some(name.trim_start_matches("block/").to_string());
+            }
+        }
+        None
+    }
+}
+
+fn animation_frame(img: DynamicImage, mcmeta: Option<Value>) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let animation = mcmeta.as_ref().and_then(|m| m.get("animation"));
+    let size = |key: &str| {
+        animation
+            .and_then(|a| a.get(key))
+            .and_then(|v| v.as_u64())
+            .map(|v| v as u32)
+    };
+    // Frames are square unless the mcmeta says otherwise, which also covers strips without an mcmeta
+    let frame_width = size("width").unwrap_or(width).clamp(1, width);
+    let frame_height = size("height").unwrap_or(frame_width).clamp(1, height);
+    if frame_width == width && frame_height == height {
+        return img;
+    }
+
+    // `frames` holds either plain indices or `{ "index": i, "time": t }` objects
+    let frame = animation
+        .and_then(|a| a.get("frames"))
+        .and_then(|f| f.as_array())
+        .and_then(|f| f.first())
+        .and_then(|f| f.as_u64().or_else(|| f.get("index")?.as_u64()))
+        .unwrap_or(0) as u32;
+    let columns = width / frame_width;
+    let (mut frame_x, mut frame_y) = (
+        frame % columns * frame_width,
+        frame / columns * frame_height,
+    );
+    if frame_y + frame_height > height {
+        frame_x = 0;
+        frame_y = 0;
+    }
+    img.crop_imm(frame_x, frame_y, frame_width, frame_height)
+}
+
+fn open_pack(path: &Path) -> Option<AssetSource> {
+    if path.is_dir() {
+        if path.join("pack.mcmeta").exists() {
+            return Some(AssetSource::Directory(path.to_path_buf()));
+        }
+    } else if path.extension()? == "zip" {
+        let mut archive = ZipArchive::new(File::open(path).ok()?).ok()?;
+        if archive.by_name("pack.mcmeta").is_ok() {
+            return Some(AssetSource::Zip(path.to_path_buf(), Mutex::new(archive)));
+        }
+    }
+    None
+}
+
+// Checks whether a dropped file or directory looks like a resource pack
+pub fn is_resource_pack(path: &Path) -> bool {
+    open_pack(path).is_some()
+}
+
+fn cached_identity(packs: &[AssetSource], vanilla: &AssetSource) -> String {
+    let key = (
+        packs
+            .iter()
+            .chain([vanilla])
+            .map(|source| source.path().to_path_buf())
+            .collect::<Vec<PathBuf>>(),
+        vanilla_version(),
+    );
+    let mut identities = IDENTITIES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((_, identity)) = identities.iter().find(|(built_for, _)| *built_for == key) {
+        return identity.clone();
+    }
+    let identity = identity(packs, vanilla);
+    identities.push((key, identity.clone()));
+    identity
+}
+
+fn identity(packs: &[AssetSource], vanilla: &AssetSource) -> String {
+    let mut hasher = DefaultHasher::new();
+    vanilla_version().hash(&mut hasher);
+    for source in packs.iter().chain([vanilla]) {
+        source.path().hash(&mut hasher);
+        match source {
+            // A directory's own mtime does not change when a file inside it is edited, every file has to be looked at
+            AssetSource::Directory(root) => hash_tree(root, &mut hasher),
+            AssetSource::Zip(path, _) => {
+                if let Ok(metadata) = fs::metadata(path) {
+                    metadata.len().hash(&mut hasher);
+                    if let Ok(modified) = metadata.modified() {
+                        modified.hash(&mut hasher);
+                    }
+                }
+            }
+        }
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+// Hashes the newest modification time, the number of files and their total size below a directory, which changes
+// whenever a file is edited, added or removed
+fn hash_tree(root: &Path, hasher: &mut DefaultHasher) {
+    let mut newest = None;
+    let (mut files, mut size) = (0u64, 0u64);
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+                continue;
+            }
+            files += 1;
+            size += metadata.len();
+            if let Ok(modified) = metadata.modified() {
+                newest = newest.max(Some(modified));
+            }
+        }
+    }
+    newest.hash(hasher);
+    files.hash(hasher);
+    size.hash(hasher);
+}
+
+pub fn vanilla_version() -> Option<String> {
+    let version = fs::read_to_string(Path::new(VANILLA_ASSETS).join(VERSION_FILE)).ok()?;
+    Some(version.trim().to_string())
+}
+
+// Accepts either a client jar or a `.minecraft/versions/<v>` folder containing `<v>.jar`
+pub fn find_client_jar(path: &Path) -> Option<PathBuf> {
+    if path.is_dir() {
+        let jar = path.join(format!("{}.jar", path.file_name()?.to_str()?));
+        if jar.exists() {
+            return Some(jar);
+        }
+    } else if path.extension()? == "jar" {
+        return Some(path.to_path_buf());
+    }
+    None
+}
+
+// The client jar the vanilla launcher keeps for a game version, if it has been installed
+pub fn launcher_jar(version: &str) -> Option<PathBuf> {
+    let minecraft = match std::env::var_os("APPDATA") {
+        Some(appdata) => PathBuf::from(appdata).join(".minecraft"),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".minecraft"),
+    };
+    find_client_jar(&minecraft.join("versions").join(version))
+}
+
+// Replaces the vanilla textures, models and blockstates with the ones in a client jar, returns the game version
+pub fn extract_client_jar(jar: &Path) -> ZipResult<String> {
+    let mut archive = ZipArchive::new(File::open(jar)?)?;
+    let version = match archive.by_name("version.json") {
+        Ok(mut file) => {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            serde_json::from_slice::<Value>(&bytes)
+                .ok()
+                .and_then(|v| Some(v.get("name")?.as_str()?.to_string()))
+        }
+        Err(_) => None,
+    }
+    .unwrap_or_else(|| jar.file_stem().unwrap().to_str().unwrap().to_string());
+
+    // Everything is extracted next to the vanilla assets first, a corrupt jar leaves the current assets untouched
+    let vanilla = Path::new(VANILLA_ASSETS);
+    let staging = Path::new(STAGING_ASSETS);
+    if staging.exists() {
+        fs::remove_dir_all(staging)?;
+    }
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = match file.enclosed_name() {
+            Some(name) => name.to_path_buf(),
+            None => continue,
+        };
+        let name_str = name.to_str().unwrap_or("").replace("\\", "/");
+        if file.is_dir() || !EXTRACTED_FOLDERS.iter().any(|f| name_str.starts_with(f)) {
+            continue;
+        }
+        let target = staging.join(name.strip_prefix("assets").unwrap());
+        fs::create_dir_all(target.parent().unwrap())?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        fs::write(target, bytes)?;
+    }
+    for folder in EXTRACTED_FOLDERS {
+        let folder = folder
+            .strip_prefix("assets/")
+            .unwrap()
+            .trim_end_matches('/');
+        let (target, extracted) = (vanilla.join(folder), staging.join(folder));
+        if !extracted.exists() {
+            continue;
+        }
+        // The old folder is only removed once the new one has taken its place
+        let old = staging.join(format!("{}.old", folder));
+        if target.exists() {
+            fs::rename(&target, &old)?;
+        }
+        fs::create_dir_all(target.parent().unwrap())?;
+        fs::rename(&extracted, &target)?;
+        if old.exists() {
+            fs::remove_dir_all(&old)?;
+        }
+    }
+    fs::remove_dir_all(staging)?;
+    fs::write(vanilla.join(VERSION_FILE), &version)?;
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+    use serde_json::json;
+
+    use super::animation_frame;
+
+    // A strip of `frames` square frames stacked top to bottom, frame `i` is filled with the value `i`
+    fn strip(size: u32, frames: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(size, size * frames, |_, y| {
+            Rgba([(y / size) as u8, 0, 0, 255])
+        }))
+    }
+
+    #[test]
+    fn square_textures_are_unchanged() {
+        let frame = animation_frame(strip(16, 1), None);
+        assert_eq!(frame.dimensions(), (16, 16));
+    }
+
+    #[test]
+    fn strips_without_mcmeta_use_the_first_frame() {
+        let frame = animation_frame(strip(16, 3), None);
+        assert_eq!(frame.dimensions(), (16, 16));
+        assert_eq!(frame.get_pixel(0, 0)[0], 0);
+    }
+
+    #[test]
+    fn frames_list_picks_the_first_listed_frame() {
+        let mcmeta = json!({ "animation": { "frames": [2, 0, 1] } });
+        assert_eq!(
+            animation_frame(strip(16, 3), Some(mcmeta)).get_pixel(0, 0)[0],
+            2
+        );
+
+        let mcmeta = json!({ "animation": { "frames": [{ "index": 1, "time": 4 }] } });
+        assert_eq!(
+            animation_frame(strip(16, 3), Some(mcmeta)).get_pixel(0, 0)[0],
+            1
+        );
+    }
+
+    #[test]
+    fn out_of_range_frames_fall_back_to_the_first() {
+        let mcmeta = json!({ "animation": { "frames": [7] } });
+        assert_eq!(
+            animation_frame(strip(16, 3), Some(mcmeta)).get_pixel(0, 0)[0],
+            0
+        );
+    }
+
+    #[test]
+    fn frame_size_comes_from_the_mcmeta() {
+        let mcmeta = json!({ "animation": { "width": 16, "height": 8 } });
+        let frame = animation_frame(strip(16, 2), Some(mcmeta));
+        assert_eq!(frame.dimensions(), (16, 8));
+    }
+}
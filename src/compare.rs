@@ -0,0 +1,641 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::Path,
+    sync::Arc,
+};
+
+use bevy::{
+    prelude::*,
+    render::camera::Camera2d,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use bevy_egui::{
+    egui::{self, Color32, Rect, Stroke},
+    EguiContext,
+};
+use futures_lite::future;
+use nbt::{Blob, Value};
+
+use crate::{
+    anvil::RegionFile,
+    inspector::BLOCK_SIZE,
+    jobs::{chunk_task, ChunkResult},
+    markers::region_files,
+    overlays::to_screen,
+    render::RenderContext,
+    tags::{list, long},
+    tiles::{visible_tiles, CHUNK_SIZE},
+    world::region_coords_from_name,
+    UIState,
+};
+
+// Cells smaller than this are merged with their neighbours, like the heatmap
+const MIN_CELL_PIXELS: f32 = 4.0;
+const ALPHA: u8 = 140;
+// Baseline tiles are only streamed while single chunks are large enough to make out on screen
+const MIN_BASELINE_PIXELS: f32 = 16.0;
+const MAX_BASELINE_TASKS: usize = 32;
+// Baseline tiles are kept a little past the screen so short pans do not reload them
+const EVICT_MARGIN: i32 = 6;
+
+// How a chunk differs between the opened world and the baseline, ordered by how much it matters when cells are
+// merged
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    // Saved again without any block changing
+    Touched,
+    Removed,
+    Added,
+    Changed,
+}
+
+impl Change {
+    const ALL: [Change; 4] = [
+        Change::Added,
+        Change::Removed,
+        Change::Changed,
+        Change::Touched,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Change::Added => "Added",
+            Change::Removed => "Removed",
+            Change::Changed => "Blocks changed",
+            Change::Touched => "Updated, same blocks",
+        }
+    }
+
+    fn color(&self) -> Color32 {
+        match self {
+            Change::Added => Color32::from_rgba_unmultiplied(0, 220, 0, ALPHA),
+            Change::Removed => Color32::from_rgba_unmultiplied(220, 0, 0, ALPHA),
+            Change::Changed => Color32::from_rgba_unmultiplied(255, 200, 0, ALPHA),
+            Change::Touched => Color32::from_rgba_unmultiplied(60, 120, 255, ALPHA / 2),
+        }
+    }
+}
+
+// Differences found in one region file and the chunks that could not be compared
+type ChunkChanges = (Vec<((i32, i32), Change)>, Vec<String>);
+// Changes of a region tagged with the comparison they belong to
+type RegionDiff = (u64, ChunkChanges);
+
+#[derive(Component)]
+pub struct BaselineSprite;
+
+#[derive(Component)]
+pub struct BaselineTask;
+
+#[derive(Default)]
+pub struct Compare {
+    baseline_path: String,
+    // Bumped for every comparison so results of an older one are dropped
+    id: u64,
+    regions: usize,
+    scanned: usize,
+    changes: HashMap<(i32, i32), Change>,
+    errors: Vec<String>,
+    hidden: bool,
+    // Share of the screen from the left that shows the baseline instead of the opened world
+    swipe: f32,
+    // Renders the baseline like the opened world, rebuilt when the tile set suffix of the mode and proto chunk
+    // setting changes
+    baseline: Option<(String, Arc<RenderContext>)>,
+    baseline_tiles: HashMap<(i32, i32), Option<Entity>>,
+    // Images of baseline tiles the swipe line runs through, registered with egui while they are painted
+    cut_images: Vec<Handle<Image>>,
+    pending: HashSet<(i32, i32)>,
+}
+
+// Baseline tiles get their own tile set, backups usually share the folder name of the world they were taken of
+fn baseline_tile_set(baseline_path: &str, suffix: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    baseline_path.hash(&mut hasher);
+    format!("compare.{:x}{}", hasher.finish(), suffix)
+}
+
+// The block states of every section, biomes and light are left out so only real edits count
+fn blocks(chunk: &Blob) -> Vec<&Value> {
+    list(chunk, "sections")
+        .into_iter()
+        .flatten()
+        .filter_map(|section| match section {
+            Value::Compound(section) => section.get("block_states"),
+            _ => None,
+        })
+        .collect()
+}
+
+// One side of a comparison, a region file missing from it has no chunks
+fn open_region(path: &Path) -> Result<Option<RegionFile>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    RegionFile::open(path)
+        .map(Some)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+}
+
+fn read_chunk(region: &mut Option<RegionFile>, local: (u32, u32)) -> nbt::Result<Option<Blob>> {
+    match region {
+        Some(region) => region.read_chunk(local),
+        None => Ok(None),
+    }
+}
+
+// Goes through a region chunk by chunk so only the two chunks being compared are decoded at a time. Chunks that
+// cannot be read on either side are left out and reported once for the region.
+fn diff_region(current: &Path, baseline: &Path) -> ChunkChanges {
+    let (region_x, region_z) = match current
+        .file_name()
+        .and_then(|name| region_coords_from_name(name.to_str()?))
+    {
+        Some(coords) => coords,
+        None => return (Vec::new(), Vec::new()),
+    };
+    let (mut current, mut baseline) = match (open_region(current), open_region(baseline)) {
+        (Ok(current), Ok(baseline)) => (current, baseline),
+        (current, baseline) => {
+            return (
+                Vec::new(),
+                current.err().into_iter().chain(baseline.err()).collect(),
+            )
+        }
+    };
+    let mut changes = Vec::new();
+    let (mut failed, mut first) = (0, None);
+    for z in 0..32 {
+        for x in 0..32 {
+            let coords = (region_x * 32 + x as i32, region_z * 32 + z as i32);
+            let (chunk, old) = match (
+                read_chunk(&mut current, (x, z)),
+                read_chunk(&mut baseline, (x, z)),
+            ) {
+                (Ok(chunk), Ok(old)) => (chunk, old),
+                (Err(e), _) | (_, Err(e)) => {
+                    failed += 1;
+                    first.get_or_insert(format!("{} {}: {}", coords.0, coords.1, e));
+                    continue;
+                }
+            };
+            let change = match (chunk, old) {
+                (None, None) => continue,
+                (Some(_), None) => Change::Added,
+                (None, Some(_)) => Change::Removed,
+                // Chunks nobody touched keep their last update, there is no need to look at their blocks
+                (Some(chunk), Some(old))
+                    if long(&chunk, "LastUpdate") == long(&old, "LastUpdate") =>
+                {
+                    continue
+                }
+                (Some(chunk), Some(old)) if blocks(&chunk) == blocks(&old) => Change::Touched,
+                _ => Change::Changed,
+            };
+            changes.push((coords, change));
+        }
+    }
+    let errors = first
+        .map(|first| {
+            format!(
+                "Could not read {} chunks of r.{}.{}, first at {}",
+                failed, region_x, region_z, first
+            )
+        })
+        .into_iter()
+        .collect();
+    (changes, errors)
+}
+
+fn start_compare(
+    commands: &mut Commands,
+    thread_pool: &AsyncComputeTaskPool,
+    compare: &mut Compare,
+    save_path: &str,
+) {
+    compare.id += 1;
+    compare.changes.clear();
+    compare.errors.clear();
+    compare.scanned = 0;
+    compare.regions = 0;
+    clear_baseline(commands, compare);
+    let current_dir = Path::new(save_path).join("region");
+    let baseline_dir = Path::new(&compare.baseline_path).join("region");
+    if !baseline_dir.exists() {
+        compare
+            .errors
+            .push(format!("No region directory at {}", baseline_dir.display()));
+        return;
+    }
+    // Regions that exist in either world, a region missing from one side is all added or all removed
+    let names = region_files(&current_dir)
+        .into_iter()
+        .chain(region_files(&baseline_dir))
+        .filter_map(|path| Some(path.file_name()?.to_owned()))
+        .collect::<BTreeSet<_>>();
+    compare.regions = names.len();
+    for name in names {
+        let id = compare.id;
+        let (current, baseline) = (current_dir.join(&name), baseline_dir.join(&name));
+        let task: Task<RegionDiff> =
+            thread_pool.spawn(async move { (id, diff_region(&current, &baseline)) });
+        commands.spawn().insert(task);
+    }
+}
+
+fn clear_baseline(commands: &mut Commands, compare: &mut Compare) {
+    for entity in compare.baseline_tiles.drain().filter_map(|(_, e)| e) {
+        commands.entity(entity).despawn();
+    }
+    compare.pending.clear();
+    compare.baseline = None;
+}
+
+pub fn handle_compare_results(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut Task<RegionDiff>)>,
+    mut compare: ResMut<Compare>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        if let Some((id, result)) = future::block_on(future::poll_once(&mut *task)) {
+            if id == compare.id {
+                compare.scanned += 1;
+                let (changes, errors) = result;
+                compare.changes.extend(changes);
+                compare.errors.extend(errors);
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn compare_window(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    mut compare: ResMut<Compare>,
+    ui_state: Res<UIState>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+    windows: Res<Windows>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+    baseline_sprites: Query<(&Transform, &Handle<Image>), With<BaselineSprite>>,
+) {
+    if ui_state.save_path.is_empty() {
+        return;
+    }
+    let mut start = false;
+    let mut clear = false;
+    egui::Window::new("Compare").show(egui_context.ctx_mut(), |ui| {
+        ui.label("Baseline world or backup");
+        ui.text_edit_singleline(&mut compare.baseline_path);
+        ui.horizontal(|ui| {
+            start = ui.button("Compare").clicked() && !compare.baseline_path.trim().is_empty();
+            clear = ui.button("Clear").clicked();
+        });
+        if compare.scanned < compare.regions {
+            ui.add(
+                egui::ProgressBar::new(compare.scanned as f32 / compare.regions as f32)
+                    .text(format!("{}/{} regions", compare.scanned, compare.regions)),
+            );
+        }
+        for error in &compare.errors {
+            ui.colored_label(Color32::RED, error);
+        }
+        if compare.regions == 0 {
+            return;
+        }
+        for change in Change::ALL {
+            let count = compare.changes.values().filter(|c| **c == change).count();
+            ui.colored_label(
+                change.color().to_opaque(),
+                format!("{} ({})", change.label(), count),
+            );
+        }
+        let mut shown = !compare.hidden;
+        ui.checkbox(&mut shown, "Show changes on map");
+        compare.hidden = !shown;
+        ui.add(egui::Slider::new(&mut compare.swipe, 0.0..=1.0).text("Baseline swipe"));
+    });
+    if start {
+        let save_path = ui_state.save_path.clone();
+        start_compare(&mut commands, &thread_pool, &mut compare, &save_path);
+    } else if clear {
+        compare.id += 1;
+        compare.regions = 0;
+        compare.scanned = 0;
+        compare.changes.clear();
+        compare.errors.clear();
+        clear_baseline(&mut commands, &mut compare);
+    }
+
+    let (transform, projection) = match cameras.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let window = windows.get_primary().unwrap();
+
+    // Sprites can only be shown or hidden whole, the baseline tiles under the swipe line are painted cut at it
+    let mut cut = Vec::new();
+    if let Some(split) = baseline_split(&compare, transform, projection, window) {
+        for (sprite_transform, image) in baseline_sprites.iter() {
+            let left = sprite_transform.translation.x - CHUNK_SIZE / 2.0;
+            if left < split && left + CHUNK_SIZE > split {
+                let top = -sprite_transform.translation.y - CHUNK_SIZE / 2.0;
+                cut.push((image.clone(), (left / BLOCK_SIZE, top / BLOCK_SIZE)));
+            }
+        }
+    }
+    for image in std::mem::take(&mut compare.cut_images) {
+        if !cut.iter().any(|(cut, _)| *cut == image) {
+            egui_context.remove_image(&image);
+        }
+    }
+    let cut = cut
+        .into_iter()
+        .map(|(image, corner)| {
+            compare.cut_images.push(image.clone());
+            (egui_context.add_image(image), corner)
+        })
+        .collect::<Vec<_>>();
+    let painter = egui_context
+        .ctx_mut()
+        .layer_painter(egui::LayerId::background());
+    let clipped = painter.with_clip_rect(Rect::from_min_max(
+        egui::pos2(0.0, 0.0),
+        egui::pos2(compare.swipe * window.width(), window.height()),
+    ));
+    let chunk_blocks = CHUNK_SIZE / BLOCK_SIZE;
+    for (texture, (x, z)) in cut {
+        let min = to_screen(window, transform, projection, (x, z));
+        let max = to_screen(
+            window,
+            transform,
+            projection,
+            (x + chunk_blocks, z + chunk_blocks),
+        );
+        clipped.add(egui::Shape::image(
+            texture,
+            Rect::from_min_max(min, max),
+            Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+            Color32::WHITE,
+        ));
+    }
+
+    if compare.swipe > 0.0 && compare.regions > 0 {
+        let x = compare.swipe * window.width();
+        painter.line_segment(
+            [egui::pos2(x, 0.0), egui::pos2(x, window.height())],
+            Stroke::new(2.0, Color32::WHITE),
+        );
+    }
+    if compare.hidden || compare.changes.is_empty() {
+        return;
+    }
+    // The most important change in each cell is shown when zoomed out
+    let visible = visible_tiles(transform, projection, window, CHUNK_SIZE);
+    let mut cell = 1;
+    while CHUNK_SIZE * cell as f32 / projection.scale < MIN_CELL_PIXELS {
+        cell *= 2;
+    }
+    let mut cells: HashMap<(i32, i32), Change> = HashMap::new();
+    for (&coords, &change) in &compare.changes {
+        if visible.contains(coords) {
+            let merged = cells
+                .entry((coords.0.div_euclid(cell), coords.1.div_euclid(cell)))
+                .or_insert(change);
+            *merged = (*merged).max(change);
+        }
+    }
+    let blocks = 16.0 * cell as f32;
+    for ((x, z), change) in cells {
+        let min = to_screen(
+            window,
+            transform,
+            projection,
+            (x as f32 * blocks, z as f32 * blocks),
+        );
+        let max = to_screen(
+            window,
+            transform,
+            projection,
+            ((x + 1) as f32 * blocks, (z + 1) as f32 * blocks),
+        );
+        painter.rect_filled(Rect::from_min_max(min, max), 0.0, change.color());
+    }
+}
+
+// World x of the swipe line, `None` while no baseline is shown
+fn baseline_split(
+    compare: &Compare,
+    transform: &Transform,
+    projection: &OrthographicProjection,
+    window: &Window,
+) -> Option<f32> {
+    let active = compare.swipe > 0.0
+        && compare.regions > 0
+        && CHUNK_SIZE / projection.scale >= MIN_BASELINE_PIXELS;
+    active.then(|| {
+        transform.translation.x
+            + (compare.swipe * window.width() - window.width() / 2.0) * projection.scale
+    })
+}
+
+// Renders the baseline world for the part of the screen left of the swipe line
+#[allow(clippy::too_many_arguments)]
+pub fn stream_baseline_tiles(
+    mut commands: Commands,
+    mut compare: ResMut<Compare>,
+    ui_state: Res<UIState>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+    asset_server: Res<AssetServer>,
+    windows: Res<Windows>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+    mut tasks: Query<(Entity, &mut Task<ChunkResult>), With<BaselineTask>>,
+    mut sprites: Query<(&Transform, &mut Visibility), With<BaselineSprite>>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        if let Some((coords, result)) = future::block_on(future::poll_once(&mut *task)) {
+            commands.entity(entity).despawn();
+            if !compare.pending.remove(&coords) {
+                continue; // Requested for a baseline that has since been cleared
+            }
+            // Failed chunks are already listed by the comparison, the opened world shows through instead
+            let sprite = match result {
+                Some(Ok(path)) => Some(
+                    commands
+                        .spawn_bundle(SpriteBundle {
+                            texture: asset_server
+                                .load(&path.split("saves").collect::<Vec<&str>>()[1][1..]),
+                            sprite: Sprite {
+                                custom_size: Some(Vec2::splat(CHUNK_SIZE)),
+                                ..default()
+                            },
+                            transform: Transform::from_xyz(
+                                coords.0 as f32 * CHUNK_SIZE + CHUNK_SIZE / 2.0,
+                                coords.1 as f32 * -CHUNK_SIZE - CHUNK_SIZE / 2.0,
+                                1.2,
+                            ),
+                            visibility: Visibility { is_visible: false },
+                            ..default()
+                        })
+                        .insert(BaselineSprite)
+                        .id(),
+                ),
+                _ => None,
+            };
+            compare.baseline_tiles.insert(coords, sprite);
+        }
+    }
+
+    // Sprites despawned elsewhere, like when the map is cleared for another render mode, are requested again
+    let compare = &mut *compare;
+    compare.baseline_tiles.retain(|_, entity| match entity {
+        Some(entity) => sprites.get(*entity).is_ok(),
+        None => true,
+    });
+
+    let (transform, projection) = match cameras.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let window = windows.get_primary().unwrap();
+    let split = baseline_split(compare, transform, projection, window);
+    // Tiles the swipe line runs through are drawn cut at the line by the compare window instead
+    for (sprite_transform, mut visibility) in sprites.iter_mut() {
+        visibility.is_visible = matches!(split, Some(split) if sprite_transform.translation.x + CHUNK_SIZE / 2.0 <= split);
+    }
+    if split.is_none() {
+        return;
+    }
+
+    let suffix = ui_state.tile_set_suffix();
+    if !matches!(&compare.baseline, Some((built, _)) if *built == suffix) {
+        for entity in compare.baseline_tiles.drain().filter_map(|(_, e)| e) {
+            commands.entity(entity).despawn();
+        }
+        compare.pending.clear();
+        match RenderContext::new(
+            &compare.baseline_path,
+            &baseline_tile_set(&compare.baseline_path, &suffix),
+            &ui_state.resource_packs,
+            ui_state.render_proto_chunks,
+            ui_state.render_mode,
+        ) {
+            Ok(ctx) => compare.baseline = Some((suffix, Arc::new(ctx))),
+            Err(e) => {
+                compare.errors.push(e.to_string());
+                compare.swipe = 0.0;
+                return;
+            }
+        }
+    }
+    let ctx = match &compare.baseline {
+        Some((_, ctx)) => ctx.clone(),
+        None => return,
+    };
+
+    let visible = visible_tiles(transform, projection, window, CHUNK_SIZE);
+    let keep = visible.expand(EVICT_MARGIN);
+    compare.baseline_tiles.retain(|coords, entity| {
+        if keep.contains(*coords) {
+            return true;
+        }
+        if let Some(entity) = entity {
+            commands.entity(*entity).despawn();
+        }
+        false
+    });
+    for coords in visible.by_distance() {
+        if compare.pending.len() >= MAX_BASELINE_TASKS {
+            break;
+        }
+        if compare.baseline_tiles.contains_key(&coords) || compare.pending.contains(&coords) {
+            continue;
+        }
+        compare.pending.insert(coords);
+        let ctx = ctx.clone();
+        let task: Task<ChunkResult> = thread_pool.spawn(async move { chunk_task(&ctx, coords) });
+        commands.spawn().insert(task).insert(BaselineTask);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs, path::Path};
+
+    use nbt::{Blob, Value};
+
+    use super::{diff_region, Change};
+
+    // A chunk with a last update time and a single section holding `blocks` as its block states
+    fn chunk(last_update: i64, blocks: &str) -> Blob {
+        let section = HashMap::from([("block_states".to_string(), Value::String(blocks.into()))]);
+        let mut blob = Blob::new();
+        blob.insert("LastUpdate", Value::Long(last_update)).unwrap();
+        blob.insert("sections", Value::List(vec![Value::Compound(section)]))
+            .unwrap();
+        blob
+    }
+
+    // Writes uncompressed chunks at the given x inside the region, one sector each
+    fn write_region(path: &Path, chunks: &[(u32, Blob)]) {
+        let mut bytes = vec![0u8; 8192];
+        for (i, (x, blob)) in chunks.iter().enumerate() {
+            let sector = 2 + i as u32;
+            let entry = *x as usize * 4;
+            bytes[entry..entry + 4].copy_from_slice(&(sector << 8 | 1).to_be_bytes());
+            let mut payload = vec![3u8];
+            blob.to_writer(&mut payload).unwrap();
+            bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&payload);
+            bytes.resize((sector as usize + 1) * 4096, 0);
+        }
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn finds_every_kind_of_change() {
+        let dir = std::env::temp_dir().join(format!("mc_viewer_compare_{}", std::process::id()));
+        fs::create_dir_all(dir.join("current")).unwrap();
+        fs::create_dir_all(dir.join("baseline")).unwrap();
+        let (current, baseline) = (
+            dir.join("current").join("r.-1.0.mca"),
+            dir.join("baseline").join("r.-1.0.mca"),
+        );
+        write_region(
+            &current,
+            &[
+                (0, chunk(10, "stone")),
+                (1, chunk(10, "stone")),
+                (3, chunk(20, "stone")),
+                (4, chunk(20, "dirt")),
+            ],
+        );
+        write_region(
+            &baseline,
+            &[
+                (0, chunk(10, "stone")),
+                (2, chunk(10, "stone")),
+                (3, chunk(10, "stone")),
+                (4, chunk(10, "stone")),
+            ],
+        );
+        let (mut changes, errors) = diff_region(&current, &baseline);
+        let (added, _) = diff_region(&current, &dir.join("baseline").join("r.-1.1.mca"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        changes.sort_by_key(|(coords, _)| *coords);
+        assert!(errors.is_empty());
+        assert!(
+            changes
+                == vec![
+                    ((-31, 0), Change::Added),
+                    ((-30, 0), Change::Removed),
+                    ((-29, 0), Change::Touched),
+                    ((-28, 0), Change::Changed),
+                ]
+        );
+        assert_eq!(added.len(), 4);
+    }
+}
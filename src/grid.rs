@@ -0,0 +1,223 @@
+use bevy::{prelude::*, render::camera::Camera2d};
+use bevy_egui::{
+    egui::{self, Color32, Rect, Stroke},
+    EguiContext,
+};
+
+use crate::{
+    overlays::to_screen,
+    tiles::{visible_tiles, TileRange, CHUNK_SIZE, REGION_SIZE},
+    UIState,
+};
+
+// Grid lines closer together than this are not drawn, at that point they would just cover the map
+const MIN_CELL_PIXELS: f32 = 8.0;
+// Cells need to be this large on screen before their coordinates fit inside them
+const MIN_LABEL_PIXELS: f32 = 64.0;
+const CHUNK_LINE: Stroke = Stroke {
+    width: 1.0,
+    color: Color32::from_rgba_premultiplied(0, 0, 0, 96),
+};
+const REGION_LINE: Stroke = Stroke {
+    width: 2.0,
+    color: Color32::from_rgba_premultiplied(200, 0, 0, 200),
+};
+const SLIME_FILL: Color32 = Color32::from_rgba_premultiplied(0, 96, 0, 96);
+
+#[derive(Default)]
+pub struct Grid {
+    chunks: bool,
+    regions: bool,
+    slime_chunks: bool,
+}
+
+// The Java `Random` the game seeds per chunk, only the parts slime chunks need
+struct JavaRandom(i64);
+
+impl JavaRandom {
+    const MULTIPLIER: i64 = 0x5DEECE66D;
+    const MASK: i64 = (1 << 48) - 1;
+
+    fn new(seed: i64) -> Self {
+        JavaRandom((seed ^ Self::MULTIPLIER) & Self::MASK)
+    }
+
+    fn next(&mut self, bits: u32) -> i32 {
+        self.0 = (self.0.wrapping_mul(Self::MULTIPLIER).wrapping_add(0xB)) & Self::MASK;
+        (self.0 >> (48 - bits)) as i32
+    }
+
+    fn next_int(&mut self, bound: i32) -> i32 {
+        loop {
+            let bits = self.next(31);
+            let value = bits % bound;
+            // Rejects the top of the range so every value is equally likely, relies on overflow like Java does
+            if bits.wrapping_sub(value).wrapping_add(bound - 1) >= 0 {
+                return value;
+            }
+        }
+    }
+}
+
+// Slimes spawn below y 40 in one in ten chunks regardless of biome, which ones follows from the world seed
+pub fn is_slime_chunk(seed: i64, (x, z): (i32, i32)) -> bool {
+    // Mixes int and long arithmetic exactly like the game, overflow included
+    let chunk_seed = seed
+        .wrapping_add(x.wrapping_mul(x).wrapping_mul(0x4c1906) as i64)
+        .wrapping_add(x.wrapping_mul(0x5ac0db) as i64)
+        .wrapping_add((z.wrapping_mul(z) as i64).wrapping_mul(0x4307a7))
+        .wrapping_add(z.wrapping_mul(0x5f24f) as i64)
+        ^ 0x3ad8025f;
+    JavaRandom::new(chunk_seed).next_int(10) == 0
+}
+
+// Vertical and horizontal lines along the edges of every cell in the range, cell size is in blocks
+fn draw_lines(
+    painter: &egui::Painter,
+    range: TileRange,
+    cell: f32,
+    stroke: Stroke,
+    screen: impl Fn((f32, f32)) -> egui::Pos2,
+) {
+    let (min_x, min_z) = (range.min.0 as f32 * cell, range.min.1 as f32 * cell);
+    let (max_x, max_z) = (
+        (range.max.0 + 1) as f32 * cell,
+        (range.max.1 + 1) as f32 * cell,
+    );
+    for x in range.min.0..=range.max.0 + 1 {
+        let x = x as f32 * cell;
+        painter.line_segment([screen((x, min_z)), screen((x, max_z))], stroke);
+    }
+    for z in range.min.1..=range.max.1 + 1 {
+        let z = z as f32 * cell;
+        painter.line_segment([screen((min_x, z)), screen((max_x, z))], stroke);
+    }
+}
+
+pub fn grid_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut grid: ResMut<Grid>,
+    ui_state: Res<UIState>,
+    windows: Res<Windows>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    if ui_state.save_path.is_empty() {
+        return;
+    }
+    let seed = match &ui_state.level {
+        Some(Ok(level)) => level.seed,
+        _ => None,
+    };
+    let ctx = egui_context.ctx_mut();
+    egui::Window::new("Grid").show(ctx, |ui| {
+        ui.checkbox(&mut grid.chunks, "Chunk borders");
+        ui.checkbox(&mut grid.regions, "Region borders");
+        ui.add_enabled(
+            seed.is_some(),
+            egui::Checkbox::new(&mut grid.slime_chunks, "Slime chunks"),
+        )
+        .on_disabled_hover_text("The world seed could not be read from level.dat");
+    });
+    let (transform, projection) = match cameras.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let window = windows.get_primary().unwrap();
+    let screen = |point| to_screen(window, transform, projection, point);
+    let painter = ctx.layer_painter(egui::LayerId::background());
+    let chunk_pixels = CHUNK_SIZE / projection.scale;
+    let region_pixels = REGION_SIZE / projection.scale;
+    let chunks = visible_tiles(transform, projection, window, CHUNK_SIZE);
+    let regions = visible_tiles(transform, projection, window, REGION_SIZE);
+
+    if let Some(seed) = seed.filter(|_| grid.slime_chunks && chunk_pixels >= MIN_CELL_PIXELS) {
+        for x in chunks.min.0..=chunks.max.0 {
+            for z in chunks.min.1..=chunks.max.1 {
+                if is_slime_chunk(seed, (x, z)) {
+                    let min = screen((x as f32 * 16.0, z as f32 * 16.0));
+                    let max = screen(((x + 1) as f32 * 16.0, (z + 1) as f32 * 16.0));
+                    painter.rect_filled(Rect::from_min_max(min, max), 0.0, SLIME_FILL);
+                }
+            }
+        }
+    }
+    if grid.chunks && chunk_pixels >= MIN_CELL_PIXELS {
+        draw_lines(&painter, chunks, 16.0, CHUNK_LINE, screen);
+        if chunk_pixels >= MIN_LABEL_PIXELS {
+            for x in chunks.min.0..=chunks.max.0 {
+                for z in chunks.min.1..=chunks.max.1 {
+                    painter.text(
+                        screen((x as f32 * 16.0, z as f32 * 16.0)) + egui::vec2(2.0, 2.0),
+                        egui::Align2::LEFT_TOP,
+                        format!("{} {}", x, z),
+                        egui::FontId::monospace(10.0),
+                        Color32::WHITE,
+                    );
+                }
+            }
+        }
+    }
+    if grid.regions && region_pixels >= MIN_CELL_PIXELS {
+        draw_lines(&painter, regions, 512.0, REGION_LINE, screen);
+        if region_pixels >= MIN_LABEL_PIXELS {
+            for x in regions.min.0..=regions.max.0 {
+                for z in regions.min.1..=regions.max.1 {
+                    painter.text(
+                        screen((x as f32 * 512.0, z as f32 * 512.0)) + egui::vec2(4.0, 4.0),
+                        egui::Align2::LEFT_TOP,
+                        format!("r.{}.{}", x, z),
+                        egui::FontId::proportional(14.0),
+                        REGION_LINE.color,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_slime_chunk;
+
+    // Expected values were checked against java.util.Random
+
+    #[test]
+    fn slime_chunks_around_origin() {
+        let found = (-6..=6)
+            .flat_map(|x| (-6..=6).map(move |z| (x, z)))
+            .filter(|chunk| is_slime_chunk(0, *chunk))
+            .collect::<Vec<(i32, i32)>>();
+        assert_eq!(
+            found,
+            vec![
+                (-5, 5),
+                (-2, 0),
+                (-1, 6),
+                (1, -3),
+                (2, -3),
+                (2, 2),
+                (2, 4),
+                (4, 2),
+                (5, -3),
+                (6, -1)
+            ]
+        );
+    }
+
+    #[test]
+    fn slime_chunks_with_negative_seed() {
+        let seed = -4172144997902289642;
+        assert!(is_slime_chunk(seed, (-6, -5)));
+        assert!(is_slime_chunk(seed, (-5, 2)));
+        assert!(!is_slime_chunk(seed, (0, 0)));
+    }
+
+    // Far from the origin `x * x` overflows an int, the game keeps the wrapped value
+    #[test]
+    fn slime_chunks_far_out() {
+        assert!(is_slime_chunk(12345, (30000, -100010)));
+        assert!(is_slime_chunk(12345, (30000, -100003)));
+        assert!(!is_slime_chunk(12345, (30000, -100000)));
+        assert!(!is_slime_chunk(12345, (30001, -100000)));
+    }
+}
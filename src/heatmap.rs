@@ -0,0 +1,292 @@
+use std::{collections::HashMap, path::Path};
+
+use bevy::{
+    prelude::*,
+    render::camera::Camera2d,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use bevy_egui::{
+    egui::{self, Color32, Rect},
+    EguiContext,
+};
+use futures_lite::future;
+
+use crate::{
+    markers::{read_chunks, region_files, short_id},
+    overlays::to_screen,
+    render::analysis,
+    tags::{int, long, string},
+    tiles::{visible_tiles, CHUNK_SIZE},
+    UIState,
+};
+
+// Generation steps in the order a chunk goes through them, older versions skip some and name a few differently
+const STATUSES: [&str; 14] = [
+    "empty",
+    "structure_starts",
+    "structure_references",
+    "biomes",
+    "noise",
+    "surface",
+    "carvers",
+    "liquid_carvers",
+    "features",
+    "initialize_light",
+    "light",
+    "spawn",
+    "heightmaps",
+    "full",
+];
+// Cells smaller than this are merged with their neighbours so zooming out does not paint millions of rectangles
+const MIN_CELL_PIXELS: f32 = 4.0;
+const ALPHA: u8 = 160;
+// An hour of play in a chunk is where the colour tops out
+const MAX_INHABITED_TIME: f32 = 72000.0;
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum HeatmapMode {
+    #[default]
+    Off,
+    Status,
+    InhabitedTime,
+    LastUpdate,
+}
+
+impl HeatmapMode {
+    const ALL: [HeatmapMode; 4] = [
+        HeatmapMode::Off,
+        HeatmapMode::Status,
+        HeatmapMode::InhabitedTime,
+        HeatmapMode::LastUpdate,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            HeatmapMode::Off => "Off",
+            HeatmapMode::Status => "Generation status",
+            HeatmapMode::InhabitedTime => "Inhabited time",
+            HeatmapMode::LastUpdate => "Last update",
+        }
+    }
+}
+
+pub struct ChunkStats {
+    pub status: String,
+    pub inhabited_time: i64,
+    pub last_update: i64,
+}
+
+// Stats of every chunk that could be read and the region files or chunks that could not
+type StatsResult = (HashMap<(i32, i32), ChunkStats>, Vec<String>);
+
+#[derive(Default)]
+pub struct Heatmap {
+    mode: HeatmapMode,
+    chunks: HashMap<(i32, i32), ChunkStats>,
+    errors: Vec<String>,
+    loading: bool,
+    // The world the stats were read from
+    save_path: String,
+    // Range of last updates in the world, newer chunks are brighter
+    last_update: (i64, i64),
+}
+
+impl Heatmap {
+    // Where a chunk sits on the colour ramp from 0 to 1 in the current mode, `None` for unknown statuses
+    fn value(&self, stats: &ChunkStats) -> Option<f32> {
+        match self.mode {
+            HeatmapMode::Off => None,
+            HeatmapMode::Status => STATUSES
+                .iter()
+                .position(|s| *s == stats.status)
+                .map(|i| i as f32 / (STATUSES.len() - 1) as f32),
+            // Most chunks are barely visited, a log scale keeps the lived in ones apart
+            HeatmapMode::InhabitedTime => Some(
+                ((stats.inhabited_time.max(0) as f32).ln_1p() / MAX_INHABITED_TIME.ln_1p())
+                    .min(1.0),
+            ),
+            HeatmapMode::LastUpdate => {
+                let (first, last) = self.last_update;
+                Some((stats.last_update - first) as f32 / (last - first).max(1) as f32)
+            }
+        }
+    }
+}
+
+// The same ramp as the analytic render modes, see through so the map still shows
+fn ramp(t: f32) -> Color32 {
+    let [r, g, b] = analysis::ramp(t).0;
+    Color32::from_rgba_unmultiplied(r, g, b, ALPHA)
+}
+
+// Reads the bookkeeping tags of every overworld chunk, including the partially generated ones tiles skip
+fn read_stats(save_path: &Path) -> StatsResult {
+    let mut stats = HashMap::new();
+    let mut errors = Vec::new();
+    for path in region_files(&save_path.join("region")) {
+        for chunk in read_chunks(&path, &mut errors) {
+            let coords = match int(&chunk, "xPos").zip(int(&chunk, "zPos")) {
+                Some(coords) => coords,
+                None => continue,
+            };
+            stats.insert(
+                coords,
+                ChunkStats {
+                    // Namespaced since 1.20
+                    status: short_id(&string(&chunk, "Status").unwrap_or_default()).to_string(),
+                    inhabited_time: long(&chunk, "InhabitedTime").unwrap_or(0),
+                    last_update: long(&chunk, "LastUpdate").unwrap_or(0),
+                },
+            );
+        }
+    }
+    (stats, errors)
+}
+
+// Reads the chunk stats in the background the first time a heatmap is shown for a world
+pub fn load_heatmap(
+    mut commands: Commands,
+    mut heatmap: ResMut<Heatmap>,
+    ui_state: Res<UIState>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+) {
+    if heatmap.mode == HeatmapMode::Off
+        || ui_state.save_path.is_empty()
+        || heatmap.save_path == ui_state.save_path
+    {
+        return;
+    }
+    heatmap.save_path = ui_state.save_path.clone();
+    heatmap.chunks.clear();
+    heatmap.errors.clear();
+    heatmap.loading = true;
+    let save_path = ui_state.save_path.clone();
+    let task: Task<(String, StatsResult)> =
+        thread_pool.spawn(async move { (save_path.clone(), read_stats(Path::new(&save_path))) });
+    commands.spawn().insert(task);
+}
+
+pub fn handle_heatmap_loads(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut Task<(String, StatsResult)>)>,
+    mut heatmap: ResMut<Heatmap>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        if let Some((save_path, result)) = future::block_on(future::poll_once(&mut *task)) {
+            if save_path == heatmap.save_path {
+                heatmap.loading = false;
+                let (chunks, errors) = result;
+                let updates = chunks.values().map(|c| c.last_update);
+                heatmap.last_update = (
+                    updates.clone().min().unwrap_or(0),
+                    updates.max().unwrap_or(0),
+                );
+                heatmap.chunks = chunks;
+                heatmap.errors = errors;
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub fn heatmap_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut heatmap: ResMut<Heatmap>,
+    ui_state: Res<UIState>,
+    windows: Res<Windows>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    if ui_state.save_path.is_empty() {
+        return;
+    }
+    let ctx = egui_context.ctx_mut();
+    egui::Window::new("Heatmap").show(ctx, |ui| {
+        for mode in HeatmapMode::ALL {
+            ui.radio_value(&mut heatmap.mode, mode, mode.label());
+        }
+        if heatmap.mode == HeatmapMode::Off {
+            return;
+        }
+        ui.separator();
+        if heatmap.loading {
+            ui.label("Reading chunks...");
+        }
+        for error in &heatmap.errors {
+            ui.colored_label(Color32::RED, error);
+        }
+        match heatmap.mode {
+            HeatmapMode::Status => {
+                for (i, status) in STATUSES.iter().enumerate() {
+                    let count = heatmap
+                        .chunks
+                        .values()
+                        .filter(|c| c.status == *status)
+                        .count();
+                    if count > 0 {
+                        ui.colored_label(
+                            ramp(i as f32 / (STATUSES.len() - 1) as f32),
+                            format!("{} ({})", status, count),
+                        );
+                    }
+                }
+            }
+            HeatmapMode::InhabitedTime => {
+                ui.colored_label(ramp(0.0), "Never visited");
+                ui.colored_label(
+                    ramp(1.0),
+                    format!("{} minutes or more", MAX_INHABITED_TIME / 1200.0),
+                );
+            }
+            HeatmapMode::LastUpdate => {
+                ui.colored_label(ramp(0.0), format!("Tick {}", heatmap.last_update.0));
+                ui.colored_label(ramp(1.0), format!("Tick {}", heatmap.last_update.1));
+            }
+            HeatmapMode::Off => {}
+        }
+    });
+    if heatmap.mode == HeatmapMode::Off {
+        return;
+    }
+    let (transform, projection) = match cameras.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let window = windows.get_primary().unwrap();
+    let visible = visible_tiles(transform, projection, window, CHUNK_SIZE);
+    // Chunks per side of a cell, a power of two so cells line up with regions
+    let mut cell = 1;
+    while CHUNK_SIZE * cell as f32 / projection.scale < MIN_CELL_PIXELS {
+        cell *= 2;
+    }
+    // Each cell shows the average of the chunks in it
+    let mut cells: HashMap<(i32, i32), (f32, u32)> = HashMap::new();
+    for (&coords, stats) in &heatmap.chunks {
+        if !visible.contains(coords) {
+            continue;
+        }
+        if let Some(value) = heatmap.value(stats) {
+            let sum = cells
+                .entry((coords.0.div_euclid(cell), coords.1.div_euclid(cell)))
+                .or_default();
+            sum.0 += value;
+            sum.1 += 1;
+        }
+    }
+    let painter = ctx.layer_painter(egui::LayerId::background());
+    let blocks = 16.0 * cell as f32;
+    for ((x, z), (sum, count)) in cells {
+        let min = to_screen(
+            window,
+            transform,
+            projection,
+            (x as f32 * blocks, z as f32 * blocks),
+        );
+        let max = to_screen(
+            window,
+            transform,
+            projection,
+            ((x + 1) as f32 * blocks, (z + 1) as f32 * blocks),
+        );
+        painter.rect_filled(Rect::from_min_max(min, max), 0.0, ramp(sum / count as f32));
+    }
+}
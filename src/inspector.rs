@@ -0,0 +1,216 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use bevy::{
+    prelude::*,
+    render::camera::Camera2d,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use bevy_egui::{egui, EguiContext};
+use futures_lite::future;
+
+use crate::{render::error::RenderError, tiles::CHUNK_SIZE, world::WorldView, UIState};
+
+// World units covered by a single block
+pub const BLOCK_SIZE: f32 = CHUNK_SIZE / 16.0;
+
+// The column a lookup was for and what was found there
+type Inspection = ((i32, i32), Result<Option<ColumnInfo>, RenderError>);
+
+// Everything worth knowing about the top of a single block column
+pub struct ColumnInfo {
+    pub position: (i32, i32, i32),
+    pub block: String,
+    pub properties: Vec<(String, String)>,
+    pub biome: String,
+    pub ocean_floor: i32,
+    pub status: String,
+    pub last_update: i64,
+}
+
+#[derive(Default)]
+pub struct Inspector {
+    // Block x and z under the mouse, `None` while the mouse is outside the window
+    pub cursor: Option<(i32, i32)>,
+    selected: Option<(i32, i32)>,
+    column: Option<Result<Option<ColumnInfo>, RenderError>>,
+}
+
+// World position under the cursor, screen space is centred on the camera and world z is the negated y axis
+pub fn cursor_world_position(
+    window: &Window,
+    transform: &Transform,
+    projection: &OrthographicProjection,
+) -> Option<Vec2> {
+    let cursor = window.cursor_position()? - Vec2::new(window.width(), window.height()) / 2.0;
+    Some(transform.translation.truncate() + cursor * projection.scale)
+}
+
+pub fn block_at(world_position: Vec2) -> (i32, i32) {
+    (
+        (world_position.x / BLOCK_SIZE).floor() as i32,
+        (-world_position.y / BLOCK_SIZE).floor() as i32,
+    )
+}
+
+// Looks up the surface block of a column, `None` if the chunk has not been generated
+pub fn inspect_column(
+    world: &WorldView,
+    x: i32,
+    z: i32,
+) -> Result<Option<ColumnInfo>, RenderError> {
+    let chunk_coords = (x.div_euclid(16), z.div_euclid(16));
+    let chunk = match world.get_chunk(chunk_coords.0, chunk_coords.1)? {
+        Some(chunk) => chunk,
+        None => return Ok(None),
+    };
+    let index = (16 * z.rem_euclid(16) + x.rem_euclid(16)) as usize;
+    let surface = chunk
+        .get_heightmap(false)
+        .ok_or(RenderError::MissingHeightmap(chunk_coords))?[index];
+    let ocean_floor = chunk
+        .get_heightmap(true)
+        .ok_or(RenderError::MissingHeightmap(chunk_coords))?[index];
+    let block = panic::catch_unwind(AssertUnwindSafe(|| {
+        chunk.get_block(x.rem_euclid(16), surface, z.rem_euclid(16))
+    }))
+    .map_err(|e| RenderError::CorruptChunk(chunk_coords, RenderError::panic_message(e)))?;
+    let mut properties = block
+        .properties
+        .iter()
+        .flatten()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect::<Vec<(String, String)>>();
+    properties.sort();
+    Ok(Some(ColumnInfo {
+        position: (x, surface, z),
+        block: format!("{}:{}", block.namespace, block.id),
+        properties,
+        biome: block.biome.clone(),
+        ocean_floor,
+        status: chunk.get_status().to_string(),
+        last_update: *chunk.get_last_update(),
+    }))
+}
+
+pub fn track_cursor(
+    windows: Res<Windows>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+    mut inspector: ResMut<Inspector>,
+) {
+    let window = windows.get_primary().unwrap();
+    inspector.cursor = cameras
+        .get_single()
+        .ok()
+        .and_then(|(transform, projection)| {
+            cursor_world_position(window, transform, projection).map(block_at)
+        });
+}
+
+// Right clicking the map looks up the column under the cursor in the background
+pub fn inspect_on_click(
+    mut commands: Commands,
+    mouse_button: Res<Input<MouseButton>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut inspector: ResMut<Inspector>,
+    ui_state: Res<UIState>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+) {
+    if !mouse_button.just_pressed(MouseButton::Right)
+        || egui_context.ctx_mut().wants_pointer_input()
+        || ui_state.save_path.is_empty()
+    {
+        return;
+    }
+    let (x, z) = match inspector.cursor {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    inspector.selected = Some((x, z));
+    inspector.column = None;
+    // Every lookup reads the region file again, the world may have changed on disk since the last one
+    let world = WorldView::new(&ui_state.save_path);
+    let task: Task<Inspection> =
+        thread_pool.spawn(async move { ((x, z), inspect_column(&world, x, z)) });
+    commands.spawn().insert(task);
+}
+
+pub fn handle_inspections(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut Task<Inspection>)>,
+    mut inspector: ResMut<Inspector>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        if let Some((column, result)) = future::block_on(future::poll_once(&mut *task)) {
+            // A slow lookup can finish after a newer one was requested
+            if inspector.selected == Some(column) {
+                inspector.column = Some(result);
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub fn inspector_window(mut egui_context: ResMut<EguiContext>, mut inspector: ResMut<Inspector>) {
+    egui::TopBottomPanel::bottom("status").show(egui_context.ctx_mut(), |ui| {
+        ui.label(match inspector.cursor {
+            Some((x, z)) => format!(
+                "X {} Z {}    Chunk {} {}    Region r.{}.{}",
+                x,
+                z,
+                x.div_euclid(16),
+                z.div_euclid(16),
+                x.div_euclid(512),
+                z.div_euclid(512)
+            ),
+            None => "Right click the map to inspect a block".to_string(),
+        });
+    });
+
+    let (x, z) = match inspector.selected {
+        Some(selected) => selected,
+        None => return,
+    };
+    let mut close = false;
+    egui::Window::new("Inspector").show(egui_context.ctx_mut(), |ui| {
+        match &inspector.column {
+            None => {
+                ui.label(format!("Looking up {} {}...", x, z));
+            }
+            Some(Ok(None)) => {
+                ui.label(format!("{} {} has not been generated", x, z));
+            }
+            Some(Err(e)) => {
+                ui.label(format!("{} {}: {}", x, z, e));
+            }
+            Some(Ok(Some(info))) => {
+                egui::Grid::new("column").show(ui, |ui| {
+                    let (x, y, z) = info.position;
+                    let rows = [
+                        ("Position", format!("{} {} {}", x, y, z)),
+                        ("Block", info.block.clone()),
+                        ("Biome", info.biome.clone()),
+                        ("Height", y.to_string()),
+                        ("Ocean floor", info.ocean_floor.to_string()),
+                        ("Chunk status", info.status.clone()),
+                        ("Last update", info.last_update.to_string()),
+                    ];
+                    for (name, value) in rows {
+                        ui.label(name);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                    for (name, value) in &info.properties {
+                        ui.label(name);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                });
+            }
+        }
+        close = ui.button("Close").clicked();
+    });
+    if close {
+        inspector.selected = None;
+        inspector.column = None;
+    }
+}
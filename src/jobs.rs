@@ -0,0 +1,527 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use bevy_egui::{egui, EguiContext};
+
+use crate::{
+    render::{
+        cached_tile, error::RenderError, is_full, is_renderable, render_chunk, stitch_region,
+        RenderContext,
+    },
+    world::region_coords_from_name,
+};
+
+// The chunk and its rendered tile or the reason it failed, `None` for chunks that do not need a tile
+pub type ChunkResult = ((i32, i32), Option<Result<String, RenderError>>);
+// A region image and the size it was requested at
+pub type RegionResult = Result<(String, u32), RenderError>;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Background,
+    // Chunks the user is looking at jump ahead of anything in the background
+    Viewport,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    fn label(&self) -> &'static str {
+        match self {
+            JobState::Queued => "Queued",
+            JobState::Running => "Running",
+            JobState::Done => "Done",
+            JobState::Failed => "Failed",
+            JobState::Cancelled => "Cancelled",
+        }
+    }
+}
+
+pub enum WorkItem {
+    Chunk((i32, i32)),
+    // A folder of chunk tiles to stitch into region images, and the size of the image wanted back
+    Region(PathBuf, u32),
+}
+
+impl WorkItem {
+    // The chunk or region the work produces a tile for
+    pub fn coords(&self) -> Option<(i32, i32)> {
+        match self {
+            WorkItem::Chunk(coords) => Some(*coords),
+            WorkItem::Region(folder, _) => region_coords_from_name(folder.file_name()?.to_str()?),
+        }
+    }
+}
+
+pub struct RenderJob {
+    pub id: u64,
+    pub name: String,
+    pub priority: Priority,
+    pub state: JobState,
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    in_flight: usize,
+    queue: VecDeque<WorkItem>,
+    // Region stitching works on finished tiles and does not need a context
+    pub ctx: Option<Arc<RenderContext>>,
+    started: Option<Instant>,
+    finished: Option<Instant>,
+}
+
+impl RenderJob {
+    pub fn is_active(&self) -> bool {
+        matches!(self.state, JobState::Queued | JobState::Running)
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        (self.completed + self.failed) as f32 / self.total as f32
+    }
+
+    // Work items finished per second since the job started
+    pub fn throughput(&self) -> Option<f32> {
+        let started = self.started?;
+        let elapsed = self.finished.unwrap_or_else(Instant::now) - started;
+        if elapsed.as_secs_f32() < 0.1 {
+            return None;
+        }
+        Some((self.completed + self.failed) as f32 / elapsed.as_secs_f32())
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        if !self.is_active() {
+            return None;
+        }
+        let throughput = self.throughput().filter(|t| *t > 0.0)?;
+        let remaining = self.total - self.completed - self.failed;
+        Some(Duration::from_secs_f32(remaining as f32 / throughput))
+    }
+}
+
+// Tasks running for a job, dropping the entity cancels the task
+#[derive(Component)]
+pub struct JobTask(pub u64);
+
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Vec<RenderJob>,
+    next_id: u64,
+}
+
+impl JobManager {
+    pub fn submit(
+        &mut self,
+        name: String,
+        priority: Priority,
+        ctx: Option<Arc<RenderContext>>,
+        items: Vec<WorkItem>,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs.push(RenderJob {
+            id,
+            name,
+            priority,
+            state: JobState::Queued,
+            total: items.len(),
+            completed: 0,
+            failed: 0,
+            in_flight: 0,
+            queue: items.into(),
+            ctx,
+            started: None,
+            finished: None,
+        });
+        // Jobs with nothing to do are finished straight away
+        self.check_finished(id);
+        id
+    }
+
+    pub fn cancel(&mut self, id: u64) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id && j.is_active()) {
+            job.state = JobState::Cancelled;
+            job.queue.clear();
+            job.ctx = None;
+            job.finished = Some(Instant::now());
+        }
+    }
+
+    // Whether a job can still be given more work, finished jobs are picked up again
+    pub fn is_open(&self, id: u64) -> bool {
+        matches!(self.get(id), Some(job) if job.state != JobState::Cancelled)
+    }
+
+    // Takes the work a job has not started on yet, work in flight still finishes
+    pub fn take_queue(&mut self, id: u64) -> Vec<WorkItem> {
+        match self.jobs.iter_mut().find(|j| j.id == id) {
+            Some(job) => {
+                job.total -= job.queue.len();
+                job.queue.drain(..).collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    // Gives a job that is still open new work, a finished job starts over so its progress only covers the new
+    // work
+    pub fn requeue(&mut self, id: u64, ctx: Option<Arc<RenderContext>>, items: Vec<WorkItem>) {
+        let job = match self
+            .jobs
+            .iter_mut()
+            .find(|j| j.id == id && j.state != JobState::Cancelled)
+        {
+            Some(job) => job,
+            None => return,
+        };
+        if !job.is_active() && !items.is_empty() {
+            job.state = JobState::Queued;
+            job.total = 0;
+            job.completed = 0;
+            job.failed = 0;
+            job.started = None;
+            job.finished = None;
+        }
+        job.total += items.len();
+        job.queue.extend(items);
+        job.ctx = ctx;
+        self.check_finished(id);
+    }
+
+    // Cancels every active job of a priority, returns the ids of the cancelled jobs
+    pub fn cancel_priority(&mut self, priority: Priority) -> HashSet<u64> {
+        let ids = self
+            .jobs
+            .iter()
+            .filter(|j| j.priority == priority && j.is_active())
+            .map(|j| j.id)
+            .collect::<HashSet<u64>>();
+        for id in &ids {
+            self.cancel(*id);
+        }
+        ids
+    }
+
+    pub fn jobs(&self) -> &[RenderJob] {
+        &self.jobs
+    }
+
+    pub fn get(&self, id: u64) -> Option<&RenderJob> {
+        self.jobs.iter().find(|j| j.id == id)
+    }
+
+    // Tasks of cancelled jobs are being dropped and no longer hold up new work
+    fn in_flight(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|j| j.is_active())
+            .map(|j| j.in_flight)
+            .sum()
+    }
+
+    // The next piece of work from the highest priority job, older jobs first
+    fn next_work(&mut self) -> Option<(u64, WorkItem, Option<Arc<RenderContext>>)> {
+        let job = self
+            .jobs
+            .iter_mut()
+            .filter(|j| j.is_active() && !j.queue.is_empty())
+            .max_by(|a, b| a.priority.cmp(&b.priority).then(b.id.cmp(&a.id)))?;
+        let item = job.queue.pop_front()?;
+        if job.state == JobState::Queued {
+            job.state = JobState::Running;
+            job.started = Some(Instant::now());
+        }
+        job.in_flight += 1;
+        Some((job.id, item, job.ctx.clone()))
+    }
+
+    // Records a finished work item, returns the job's context if that was the last of its work
+    pub fn complete(&mut self, id: u64, success: bool) -> Option<Arc<RenderContext>> {
+        let job = self.jobs.iter_mut().find(|j| j.id == id)?;
+        job.in_flight = job.in_flight.saturating_sub(1);
+        if success {
+            job.completed += 1;
+        } else {
+            job.failed += 1;
+        }
+        let ctx = job.ctx.clone();
+        if self.check_finished(id) {
+            ctx
+        } else {
+            None
+        }
+    }
+
+    // A task of a cancelled job was dropped before it finished
+    fn dropped(&mut self, id: u64) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+            job.in_flight = job.in_flight.saturating_sub(1);
+        }
+    }
+
+    fn check_finished(&mut self, id: u64) -> bool {
+        match self.jobs.iter_mut().find(|j| j.id == id) {
+            Some(job) if job.is_active() && job.queue.is_empty() && job.in_flight == 0 => {
+                job.state = if job.failed > 0 {
+                    JobState::Failed
+                } else {
+                    JobState::Done
+                };
+                job.finished = Some(Instant::now());
+                // Finished jobs are kept for the jobs window, their decoded regions are not
+                job.ctx = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // A cancelled viewport job is replaced on the next camera move, once its tasks are gone there is nothing left
+    // to show
+    fn prune(&mut self) {
+        self.jobs.retain(|j| {
+            !(j.priority == Priority::Viewport
+                && j.state == JobState::Cancelled
+                && j.in_flight == 0)
+        });
+    }
+
+    pub fn clear_finished(&mut self) {
+        self.jobs.retain(|j| j.is_active());
+    }
+}
+
+// Keeps the thread pool busy with work from the job queue without flooding it, so new high priority work never
+// waits behind thousands of already spawned background tasks
+pub fn dispatch_jobs(
+    mut commands: Commands,
+    mut jobs: ResMut<JobManager>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+    tasks: Query<(Entity, &JobTask)>,
+) {
+    for (entity, task) in tasks.iter() {
+        // Tasks of pruned jobs are dropped as well
+        let cancelled = match jobs.get(task.0) {
+            Some(job) => job.state == JobState::Cancelled,
+            None => true,
+        };
+        if cancelled {
+            commands.entity(entity).despawn();
+            jobs.dropped(task.0);
+        }
+    }
+    jobs.prune();
+
+    let limit = thread_pool.thread_num() * 2;
+    while jobs.in_flight() < limit {
+        let (id, item, ctx) = match jobs.next_work() {
+            Some(work) => work,
+            None => break,
+        };
+        match (item, ctx) {
+            (WorkItem::Chunk(chunk_coords), Some(ctx)) => {
+                let task: Task<ChunkResult> =
+                    thread_pool.spawn(async move { chunk_task(&ctx, chunk_coords) });
+                commands.spawn().insert(task).insert(JobTask(id));
+            }
+            (WorkItem::Region(region_folder, size), _) => {
+                // Region folders live directly in the save's tile folder
+                let save_name = region_folder
+                    .parent()
+                    .and_then(|p| p.file_name())
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let task: Task<RegionResult> = thread_pool.spawn(async move {
+                    stitch_region(&save_name, &region_folder, size).map(|path| (path, size))
+                });
+                commands.spawn().insert(task).insert(JobTask(id));
+            }
+            (WorkItem::Chunk(_), None) => {
+                jobs.complete(id, false);
+            }
+        }
+    }
+}
+
+// Renders a chunk unless an up to date tile already exists
+pub fn chunk_task(ctx: &RenderContext, chunk_coords: (i32, i32)) -> ChunkResult {
+    (chunk_coords, render_or_cached(ctx, chunk_coords))
+}
+
+fn render_or_cached(
+    ctx: &RenderContext,
+    chunk_coords: (i32, i32),
+) -> Option<Result<String, RenderError>> {
+    let region_coords = (chunk_coords.0.div_euclid(32), chunk_coords.1.div_euclid(32));
+    let chunk = match ctx.world.get_chunk(chunk_coords.0, chunk_coords.1) {
+        Ok(Some(chunk)) => chunk,
+        Ok(None) => return None, // Chunk or region file does not exist
+        Err(e) => return Some(Err(e)),
+    };
+    if !is_renderable(chunk.get_status(), ctx.proto_chunks) {
+        return None; // Chunk not generated far enough to have a surface
+    }
+    // Proto chunks can be saved before their heightmaps are
+    if !is_full(chunk.get_status()) && chunk.get_heightmap(false).is_none() {
+        return None;
+    }
+    Some(
+        match cached_tile(&ctx.save_name, chunk_coords, *chunk.get_last_update()) {
+            Ok(Some(path)) => Ok(path),
+            Ok(None) => render_chunk(&chunk, region_coords, ctx),
+            Err(e) => Err(e),
+        },
+    )
+}
+
+pub fn jobs_window(mut egui_context: ResMut<EguiContext>, mut jobs: ResMut<JobManager>) {
+    if jobs.jobs().is_empty() {
+        return;
+    }
+    let mut cancel = None;
+    let mut clear = false;
+    egui::Window::new("Render Jobs").show(egui_context.ctx_mut(), |ui| {
+        for job in jobs.jobs().iter().rev() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} ({})", job.name, job.state.label()));
+                if job.is_active() && ui.small_button("Cancel").clicked() {
+                    cancel = Some(job.id);
+                }
+            });
+            ui.add(egui::ProgressBar::new(job.progress()).text(format!(
+                "{}/{}{}",
+                job.completed + job.failed,
+                job.total,
+                if job.failed > 0 {
+                    format!(", {} failed", job.failed)
+                } else {
+                    String::new()
+                }
+            )));
+            let mut stats = Vec::new();
+            if let Some(throughput) = job.throughput() {
+                stats.push(format!("{:.1}/s", throughput));
+            }
+            if let Some(eta) = job.eta() {
+                stats.push(format!("{}s left", eta.as_secs()));
+            }
+            if !stats.is_empty() {
+                ui.label(stats.join(", "));
+            }
+            ui.separator();
+        }
+        clear = ui.button("Clear Finished").clicked();
+    });
+    if let Some(id) = cancel {
+        jobs.cancel(id);
+    }
+    if clear {
+        jobs.clear_finished();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JobManager, JobState, Priority, WorkItem};
+
+    fn chunks(count: i32) -> Vec<WorkItem> {
+        (0..count).map(|x| WorkItem::Chunk((x, 0))).collect()
+    }
+
+    fn state(jobs: &JobManager, id: u64) -> JobState {
+        jobs.get(id).unwrap().state
+    }
+
+    #[test]
+    fn empty_jobs_finish_straight_away() {
+        let mut jobs = JobManager::default();
+        let id = jobs.submit("Empty".into(), Priority::Background, None, Vec::new());
+        assert!(state(&jobs, id) == JobState::Done);
+    }
+
+    #[test]
+    fn jobs_run_until_every_item_completes() {
+        let mut jobs = JobManager::default();
+        let id = jobs.submit("Render".into(), Priority::Background, None, chunks(2));
+        assert!(state(&jobs, id) == JobState::Queued);
+
+        jobs.next_work().unwrap();
+        assert!(state(&jobs, id) == JobState::Running);
+        jobs.next_work().unwrap();
+        assert!(jobs.next_work().is_none());
+
+        jobs.complete(id, true);
+        assert!(state(&jobs, id) == JobState::Running);
+        jobs.complete(id, false);
+        assert!(state(&jobs, id) == JobState::Failed);
+        let job = jobs.get(id).unwrap();
+        assert_eq!((job.completed, job.failed, job.total), (1, 1, 2));
+    }
+
+    #[test]
+    fn viewport_work_goes_first() {
+        let mut jobs = JobManager::default();
+        let background = jobs.submit("Render".into(), Priority::Background, None, chunks(1));
+        let viewport = jobs.submit("Viewport".into(), Priority::Viewport, None, chunks(1));
+        assert_eq!(jobs.next_work().unwrap().0, viewport);
+        assert_eq!(jobs.next_work().unwrap().0, background);
+    }
+
+    #[test]
+    fn cancelled_jobs_take_no_more_work() {
+        let mut jobs = JobManager::default();
+        let id = jobs.submit("Render".into(), Priority::Background, None, chunks(3));
+        jobs.next_work().unwrap();
+        jobs.cancel(id);
+        assert!(state(&jobs, id) == JobState::Cancelled);
+        assert!(!jobs.is_open(id));
+        assert!(jobs.next_work().is_none());
+
+        jobs.requeue(id, None, chunks(2));
+        assert!(state(&jobs, id) == JobState::Cancelled);
+        assert!(jobs.next_work().is_none());
+    }
+
+    #[test]
+    fn requeue_restarts_finished_jobs() {
+        let mut jobs = JobManager::default();
+        let id = jobs.submit("Viewport".into(), Priority::Viewport, None, chunks(1));
+        jobs.next_work().unwrap();
+        jobs.complete(id, true);
+        assert!(state(&jobs, id) == JobState::Done);
+
+        jobs.requeue(id, None, chunks(2));
+        assert!(state(&jobs, id) == JobState::Queued);
+        let job = jobs.get(id).unwrap();
+        assert_eq!((job.completed, job.total), (0, 2));
+    }
+
+    #[test]
+    fn taking_the_queue_leaves_work_in_flight() {
+        let mut jobs = JobManager::default();
+        let id = jobs.submit("Viewport".into(), Priority::Viewport, None, chunks(3));
+        jobs.next_work().unwrap();
+        assert_eq!(jobs.take_queue(id).len(), 2);
+        assert_eq!(jobs.get(id).unwrap().total, 1);
+        assert!(state(&jobs, id) == JobState::Running);
+        jobs.complete(id, true);
+        assert!(state(&jobs, id) == JobState::Done);
+    }
+}
@@ -0,0 +1,313 @@
+use std::{collections::BTreeMap, path::Path};
+
+use bevy::{prelude::*, tasks::AsyncComputeTaskPool};
+use bevy_egui::{egui, EguiContext};
+use nbt::Value;
+
+use crate::{
+    extract_assets,
+    tags::{compound, int, long, read_gzip, string},
+    UIState,
+};
+
+// First data version after the flattening, block ids replaced numeric ids
+const FLATTENING: i32 = 1451;
+// First data version of the 1.18 chunk format, the `Level` wrapper is gone and worlds go down to y -64
+const CAVES_AND_CLIFFS: i32 = 2844;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChunkFormat {
+    PreFlattening,
+    Flattened,
+    CavesAndCliffs,
+}
+
+impl ChunkFormat {
+    pub fn from_data_version(data_version: i32) -> Self {
+        if data_version < FLATTENING {
+            ChunkFormat::PreFlattening
+        } else if data_version < CAVES_AND_CLIFFS {
+            ChunkFormat::Flattened
+        } else {
+            ChunkFormat::CavesAndCliffs
+        }
+    }
+
+    pub fn min_y(&self) -> i32 {
+        match self {
+            ChunkFormat::PreFlattening | ChunkFormat::Flattened => 0,
+            ChunkFormat::CavesAndCliffs => -64,
+        }
+    }
+
+    // simple_anvil only reads chunks written by 1.18 and later
+    pub fn is_supported(&self) -> bool {
+        *self == ChunkFormat::CavesAndCliffs
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChunkFormat::PreFlattening => "pre 1.13",
+            ChunkFormat::Flattened => "1.13 to 1.17",
+            ChunkFormat::CavesAndCliffs => "1.18+",
+        }
+    }
+}
+
+// The parts of a world's level.dat the viewer cares about
+#[derive(Default, Clone)]
+pub struct Level {
+    pub name: Option<String>,
+    pub version_name: Option<String>,
+    pub data_version: Option<i32>,
+    pub seed: Option<i64>,
+    pub spawn: Option<(i32, i32, i32)>,
+    pub time: Option<i64>,
+    pub day_time: Option<i64>,
+    pub game_rules: BTreeMap<String, String>,
+}
+
+impl Level {
+    pub fn read<P: AsRef<Path>>(save_path: P) -> nbt::Result<Self> {
+        let blob = read_gzip(save_path.as_ref().join("level.dat"))?;
+        let data = match compound(&blob, "Data") {
+            Some(data) => data,
+            None => return Ok(Level::default()),
+        };
+        Ok(Level {
+            name: string(data, "LevelName"),
+            version_name: compound(data, "Version").and_then(|v| string(v, "Name")),
+            data_version: int(data, "DataVersion"),
+            // Moved into the world generation settings in 1.16
+            seed: compound(data, "WorldGenSettings")
+                .and_then(|settings| long(settings, "seed"))
+                .or_else(|| long(data, "RandomSeed")),
+            spawn: int(data, "SpawnX")
+                .zip(int(data, "SpawnY"))
+                .zip(int(data, "SpawnZ"))
+                .map(|((x, y), z)| (x, y, z)),
+            time: long(data, "Time"),
+            day_time: long(data, "DayTime"),
+            game_rules: compound(data, "GameRules")
+                .into_iter()
+                .flatten()
+                .filter_map(|(name, value)| match value {
+                    Value::String(value) => Some((name.clone(), value.clone())),
+                    _ => None,
+                })
+                .collect(),
+        })
+    }
+
+    // Worlds without a level.dat are assumed to be from a current version
+    pub fn chunk_format(&self) -> ChunkFormat {
+        self.data_version
+            .map(ChunkFormat::from_data_version)
+            .unwrap_or(ChunkFormat::CavesAndCliffs)
+    }
+}
+
+pub fn world_info_window(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    mut ui_state: ResMut<UIState>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+) {
+    let level = match &ui_state.level {
+        Some(level) => level,
+        None => return,
+    };
+    let mut extract = None;
+    egui::Window::new("World Info").show(egui_context.ctx_mut(), |ui| {
+        let level = match level {
+            Ok(level) => level,
+            Err(e) => {
+                ui.label(format!("Could not read level.dat: {}", e));
+                return;
+            }
+        };
+        let unknown = || "unknown".to_string();
+        let format = level.chunk_format();
+        egui::Grid::new("level").show(ui, |ui| {
+            let rows = [
+                ("Name", level.name.clone().unwrap_or_else(unknown)),
+                (
+                    "Version",
+                    format!(
+                        "{} (data version {})",
+                        level.version_name.clone().unwrap_or_else(unknown),
+                        level
+                            .data_version
+                            .map(|v| v.to_string())
+                            .unwrap_or_else(unknown)
+                    ),
+                ),
+                ("Chunk format", format.label().to_string()),
+                (
+                    "Seed",
+                    level.seed.map(|s| s.to_string()).unwrap_or_else(unknown),
+                ),
+                (
+                    "Spawn",
+                    level
+                        .spawn
+                        .map(|(x, y, z)| format!("{} {} {}", x, y, z))
+                        .unwrap_or_else(unknown),
+                ),
+                (
+                    "Game time",
+                    level
+                        .time
+                        .map(|t| format!("{} ticks, day {}", t, t / 24000))
+                        .unwrap_or_else(unknown),
+                ),
+                (
+                    "Time of day",
+                    level
+                        .day_time
+                        .map(|t| (t % 24000).to_string())
+                        .unwrap_or_else(unknown),
+                ),
+            ];
+            for (name, value) in rows {
+                ui.label(name);
+                ui.label(value);
+                ui.end_row();
+            }
+        });
+        if !format.is_supported() {
+            ui.colored_label(
+                egui::Color32::RED,
+                "Chunks in this world are too old to render, open it in 1.18 or later to upgrade it",
+            );
+        }
+        if let Some(version) = &level.version_name {
+            if ui_state.asset_version.as_ref() != Some(version) && !ui_state.extracting_assets {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!(
+                        "Vanilla assets do not match {}, drag its client jar here for exact textures",
+                        version
+                    ),
+                );
+                if let Some(jar) = &ui_state.launcher_jar {
+                    if ui
+                        .button(format!("Extract assets from {}", jar.display()))
+                        .on_hover_text("Replaces the vanilla assets, tiles are rendered again")
+                        .clicked()
+                    {
+                        extract = Some(jar.clone());
+                    }
+                }
+            }
+        }
+        egui::CollapsingHeader::new(format!("Game rules ({})", level.game_rules.len())).show(
+            ui,
+            |ui| {
+                egui::Grid::new("game_rules").show(ui, |ui| {
+                    for (name, value) in &level.game_rules {
+                        ui.label(name);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                });
+            },
+        );
+    });
+    if let Some(jar) = extract {
+        extract_assets(&mut commands, &thread_pool, &mut ui_state, jar);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use nbt::{Blob, Value};
+
+    use super::{ChunkFormat, Level};
+
+    // Writes a level.dat with the given `Data` compound into a fresh temporary world folder
+    fn world_with(name: &str, data: Option<HashMap<String, Value>>) -> std::path::PathBuf {
+        let world = std::env::temp_dir().join(format!("mc_viewer_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&world).unwrap();
+        let mut blob = Blob::new();
+        if let Some(data) = data {
+            blob.insert("Data", Value::Compound(data)).unwrap();
+        }
+        let mut file = fs::File::create(world.join("level.dat")).unwrap();
+        blob.to_gzip_writer(&mut file).unwrap();
+        world
+    }
+
+    #[test]
+    fn reads_current_level() {
+        let mut data = HashMap::new();
+        data.insert("LevelName".to_string(), Value::String("Test".into()));
+        data.insert("DataVersion".to_string(), Value::Int(3120));
+        data.insert(
+            "Version".to_string(),
+            Value::Compound(HashMap::from([(
+                "Name".to_string(),
+                Value::String("1.19.2".into()),
+            )])),
+        );
+        data.insert(
+            "WorldGenSettings".to_string(),
+            Value::Compound(HashMap::from([("seed".to_string(), Value::Long(-42))])),
+        );
+        data.insert("RandomSeed".to_string(), Value::Long(7));
+        data.insert("SpawnX".to_string(), Value::Int(10));
+        data.insert("SpawnY".to_string(), Value::Int(64));
+        data.insert("SpawnZ".to_string(), Value::Int(-5));
+        data.insert(
+            "GameRules".to_string(),
+            Value::Compound(HashMap::from([
+                ("keepInventory".to_string(), Value::String("true".into())),
+                ("ignored".to_string(), Value::Int(1)),
+            ])),
+        );
+        let world = world_with("level_current", Some(data));
+        let level = Level::read(&world).unwrap();
+        fs::remove_dir_all(&world).unwrap();
+
+        assert_eq!(level.name.as_deref(), Some("Test"));
+        assert_eq!(level.version_name.as_deref(), Some("1.19.2"));
+        assert_eq!(level.data_version, Some(3120));
+        assert_eq!(level.seed, Some(-42));
+        assert_eq!(level.spawn, Some((10, 64, -5)));
+        assert_eq!(level.game_rules.len(), 1);
+        assert_eq!(level.game_rules["keepInventory"], "true");
+        assert!(level.chunk_format() == ChunkFormat::CavesAndCliffs);
+    }
+
+    #[test]
+    fn old_levels_keep_the_seed_in_data() {
+        let mut data = HashMap::new();
+        data.insert("DataVersion".to_string(), Value::Int(1343));
+        data.insert("RandomSeed".to_string(), Value::Long(7));
+        let world = world_with("level_old", Some(data));
+        let level = Level::read(&world).unwrap();
+        fs::remove_dir_all(&world).unwrap();
+
+        assert_eq!(level.seed, Some(7));
+        assert_eq!(level.spawn, None);
+        assert!(level.chunk_format() == ChunkFormat::PreFlattening);
+    }
+
+    #[test]
+    fn missing_data_is_an_empty_level() {
+        let world = world_with("level_empty", None);
+        let level = Level::read(&world).unwrap();
+        fs::remove_dir_all(&world).unwrap();
+
+        assert_eq!(level.name, None);
+        assert!(level.chunk_format() == ChunkFormat::CavesAndCliffs);
+    }
+
+    #[test]
+    fn missing_level_dat_is_an_error() {
+        let world = std::env::temp_dir().join(format!("mc_viewer_no_level_{}", std::process::id()));
+        assert!(Level::read(&world).is_err());
+    }
+}
@@ -1,93 +1,129 @@
 //#![windows_subsystem = "windows"]
 
 use std::{
-    fs::{self, DirEntry},
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError},
 };
 
 use bevy::{
     asset::AssetServerSettings,
-    ecs::query::WorldQuery,
-    input::mouse::{MouseMotion, MouseWheel},
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
     prelude::*,
     render::camera::Camera2d,
     tasks::{AsyncComputeTaskPool, Task},
-    utils::HashMap,
 };
 
 use bevy_egui::{egui, EguiContext, EguiPlugin};
 use futures_lite::future;
-use image::{open, Rgba, RgbaImage};
-use render::render_chunk;
-use simple_anvil::region::Region;
+use jobs::{ChunkResult, JobManager, JobTask, Priority, RegionResult, WorkItem};
+use level::Level;
+use markers::MarkerSprite;
+use render::{error::RenderError, mode::RenderMode, RenderContext, ERROR_TILE, REGION_IMAGE_SIZES};
+use tiles::{spawn_region_sprite, Lod, TileManager, CHUNK_SIZE};
 
+mod anvil;
+mod assets;
+mod compare;
+mod grid;
+mod heatmap;
+mod inspector;
+mod jobs;
+mod level;
+mod markers;
+mod modes;
+mod navigation;
+mod overlays;
 mod render;
+mod search;
+mod tags;
+mod tiles;
+mod world;
 
+// 32 pixels per block at the closest, a whole region in a few dozen pixels at the furthest
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 512.0;
+// Scale change per line scrolled
+const ZOOM_STEP: f32 = 1.1;
+// Trackpads scroll in pixels, roughly how many make up one line of a mouse wheel
+const PIXELS_PER_LINE: f32 = 20.0;
+
+// Projection scale of the camera, world units per pixel
 #[derive(Clone, PartialEq)]
-enum Zoom {
-    One,
-    Two,
-    Three,
-    Four,
-}
+struct Zoom(f32);
 
 impl Default for Zoom {
     fn default() -> Self {
-        Zoom::One
+        Zoom(1.0)
     }
 }
 
 #[derive(Default, Clone)]
-struct UIState {
+pub struct UIState {
     save_name: String,
     save_path: String,
-    loading: bool,
     zoom: Zoom,
-    rendering_count: u32,
     rendering_viewport: bool,
     viewport_moved: bool,
+    // Highest priority first, vanilla assets are always used last
+    resource_packs: Vec<String>,
+    // Packs the last render could not open
+    pack_errors: Vec<String>,
+    asset_version: Option<String>,
+    extracting_assets: bool,
+    // The launcher's client jar for the opened world's version, if it is installed
+    launcher_jar: Option<PathBuf>,
+    render_report: Option<String>,
+    failed_chunks: Vec<String>,
+    level: Option<Result<Level, String>>,
+    // Also render chunks that are not fully generated but already have their surface
+    render_proto_chunks: bool,
+    render_mode: RenderMode,
 }
 
 impl UIState {
-    pub fn zoom_in(&mut self) -> bool {
-        let end = self.zoom != Zoom::One;
-        self.zoom = match self.zoom {
-            Zoom::One => Zoom::One,
-            Zoom::Two => Zoom::One,
-            Zoom::Three => Zoom::Two,
-            Zoom::Four => Zoom::Three,
-        };
-        end
+    // Name of the folder in `saves` the tiles of the current world, render mode and proto chunk setting are kept in
+    pub fn tile_set(&self) -> String {
+        format!("{}{}", self.save_name, self.tile_set_suffix())
     }
 
-    pub fn zoom_out(&mut self) -> bool {
-        let end = self.zoom != Zoom::Four;
-        self.zoom = match self.zoom {
-            Zoom::One => Zoom::Two,
-            Zoom::Two => Zoom::Three,
-            Zoom::Three => Zoom::Four,
-            Zoom::Four => Zoom::Four,
+    // Partially generated chunks show up striped, tiles rendered with them are kept apart from the ones without
+    pub fn tile_set_suffix(&self) -> String {
+        let proto = if self.render_proto_chunks {
+            ".proto"
+        } else {
+            ""
         };
-        end
+        format!("{}{}", self.render_mode.suffix(), proto)
     }
 
-    pub fn zoom_enumerated(&self) -> u32 {
-        match self.zoom {
-            Zoom::One => 1,
-            Zoom::Two => 2,
-            Zoom::Three => 4,
-            Zoom::Four => 8,
-        }
+    // Scales the zoom by `factor` within the zoom limits, returns false when already at the limit
+    pub fn zoom_by(&mut self, factor: f32) -> bool {
+        let scale = (self.zoom.0 * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let changed = scale != self.zoom.0;
+        self.zoom.0 = scale;
+        changed
     }
-}
 
-#[derive(Component)]
-struct ChunkSpriteCoords((u32, u32));
-#[derive(Component)]
-struct ChunkSpriteRegionCoords((i32, i32));
+    pub fn zoom_scale(&self) -> f32 {
+        self.zoom.0
+    }
+}
 
 fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.len() == 3 && args[1] == "extract-assets" {
+        match assets::find_client_jar(Path::new(&args[2])) {
+            Some(jar) => match assets::extract_client_jar(&jar) {
+                Ok(version) => println!("Extracted assets for Minecraft {}", version),
+                Err(e) => println!("Failed to extract assets from {}: {}", jar.display(), e),
+            },
+            None => println!("{} is not a client jar", args[2]),
+        }
+        return;
+    }
+
     App::new()
         .insert_resource(AssetServerSettings {
             asset_folder: "./saves".to_string(),
@@ -96,246 +132,263 @@ fn main() {
         .add_plugins(DefaultPlugins)
         .add_plugin(EguiPlugin)
         .init_resource::<UIState>()
+        .init_resource::<JobManager>()
+        .init_resource::<RenderContexts>()
+        .init_resource::<TileManager>()
+        .init_resource::<inspector::Inspector>()
+        .init_resource::<navigation::Navigation>()
+        .init_resource::<markers::Markers>()
+        .init_resource::<overlays::Overlays>()
+        .init_resource::<grid::Grid>()
+        .init_resource::<heatmap::Heatmap>()
+        .init_resource::<modes::ModeSettings>()
+        .init_resource::<search::BlockSearch>()
+        .init_resource::<compare::Compare>()
         .add_startup_system(setup)
         .add_system(egui)
+        .add_system(jobs::jobs_window)
+        .add_system(jobs::dispatch_jobs)
+        .add_system(tiles::stream_tiles)
+        .add_system(inspector::track_cursor)
+        .add_system(inspector::inspect_on_click)
+        .add_system(inspector::handle_inspections)
+        .add_system(inspector::inspector_window)
+        .add_system(navigation::navigation_window)
+        .add_system(level::world_info_window)
+        .add_system(markers::load_markers)
+        .add_system(markers::handle_marker_loads)
+        .add_system(markers::sync_marker_sprites)
+        .add_system(markers::markers_window)
+        .add_system(overlays::reload_overlays)
+        .add_system(overlays::draw_overlays)
+        .add_system(grid::grid_window)
+        .add_system(heatmap::load_heatmap)
+        .add_system(heatmap::handle_heatmap_loads)
+        .add_system(heatmap::heatmap_window)
+        .add_system(modes::render_mode_window)
+        .add_system(search::handle_search_results)
+        .add_system(search::block_search_window)
+        .add_system(compare::handle_compare_results)
+        .add_system(compare::compare_window)
+        .add_system(compare::stream_baseline_tiles)
         .add_system(grab_mouse)
         .add_system(drag_folder)
         .add_system(handle_per_chunk_images)
         .add_system(handle_per_region_images)
+        .add_system(handle_asset_extraction)
         .add_system(zoom)
         .run();
 }
 
-fn setup(mut commands: Commands) {
+fn setup(mut commands: Commands, mut ui_state: ResMut<UIState>) {
     commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    if let Err(e) = render::write_error_tile() {
+        ui_state
+            .failed_chunks
+            .push(format!("Could not write the error tile: {}", e));
+    }
+    ui_state.asset_version = assets::vanilla_version();
 }
 
 fn egui(
     mut egui_context: ResMut<EguiContext>,
     mut ui_state: ResMut<UIState>,
-    commands: Commands,
-    thread_pool: Res<AsyncComputeTaskPool>,
-    transforms: ParamSet<(Query<&mut Transform, With<Camera2d>>, Query<&Transform>)>,
-    windows: Res<Windows>,
-    tiles: Query<(Entity, With<Sprite>)>,
+    mut jobs: ResMut<JobManager>,
+    mut contexts: ResMut<RenderContexts>,
+    mut tiles: ResMut<TileManager>,
+    mut commands: Commands,
+    sprites: Query<Entity, (With<Sprite>, Without<MarkerSprite>)>,
 ) {
-    let mut load = false;
     let mut optimize = false;
     let mut all = false;
     egui::Window::new("Drag Save Directory").show(egui_context.ctx_mut(), |ui| {
         ui.text_edit_singleline(&mut ui_state.save_path);
-        if ui_state.save_path != "" {
-            ui.checkbox(&mut ui_state.rendering_viewport, "Render Current Viewport?");
+        if !ui_state.save_path.is_empty() {
+            if ui
+                .checkbox(&mut ui_state.rendering_viewport, "Render Current Viewport?")
+                .changed()
+            {
+                ui_state.viewport_moved = true;
+            }
+            if ui
+                .checkbox(
+                    &mut ui_state.render_proto_chunks,
+                    "Render Partially Generated Chunks?",
+                )
+                .changed()
+            {
+                // The tiles on the map belong to the other tile set, stream them again like a mode switch does
+                jobs.cancel_priority(Priority::Viewport);
+                jobs.cancel_priority(Priority::Background);
+                for entity in sprites.iter() {
+                    commands.entity(entity).despawn();
+                }
+                tiles.clear();
+                ui_state.viewport_moved = true;
+            }
             optimize = ui.button("Optimize Tiles").clicked();
             all = ui.button("Render All Chunks").clicked();
-            ui.label(format!(
-                "Currently rendering: {} chunks",
-                ui_state.rendering_count
-            ));
         }
     });
-
-    if ui_state.rendering_viewport {
-        if !ui_state.loading && ui_state.viewport_moved {
-            ui_state.loading = true;
-            ui_state.viewport_moved = false;
-            determine_chunks(commands, thread_pool, transforms, windows, ui_state);
+    let mut close_report = false;
+    if let Some(report) = &ui_state.render_report {
+        egui::Window::new("Render Report").show(egui_context.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| ui.monospace(report));
+            close_report = ui.button("Close").clicked();
+        });
+    }
+    if close_report {
+        ui_state.render_report = None;
+    }
+    let mut clear_failures = false;
+    if !ui_state.failed_chunks.is_empty() {
+        egui::Window::new("Failed Chunks").show(egui_context.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for failure in &ui_state.failed_chunks {
+                        ui.label(failure);
+                    }
+                });
+            clear_failures = ui.button("Clear").clicked();
+        });
+    }
+    if clear_failures {
+        ui_state.failed_chunks.clear();
+    }
+    egui::Window::new("Resource Packs").show(egui_context.ctx_mut(), |ui| {
+        if ui_state.extracting_assets {
+            ui.label("Extracting vanilla assets...");
+        } else {
+            ui.label(match &ui_state.asset_version {
+                Some(version) => format!("Vanilla assets: {}", version),
+                None => "Vanilla assets: bundled, drag a client jar here to update".to_string(),
+            });
+        }
+        if ui_state.resource_packs.is_empty() {
+            ui.label("Drag a resource pack folder or zip here");
         }
-    } else if all {
-        ui_state.loading = true;
-        render_all(commands, thread_pool, ui_state);
+        for error in &ui_state.pack_errors {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        let mut raise = None;
+        let mut remove = None;
+        for (i, pack) in ui_state.resource_packs.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.small_button("^").clicked() && i > 0 {
+                    raise = Some(i);
+                }
+                if ui.small_button("x").clicked() {
+                    remove = Some(i);
+                }
+                ui.label(pack);
+            });
+        }
+        if let Some(i) = raise {
+            ui_state.resource_packs.swap(i, i - 1);
+        }
+        if let Some(i) = remove {
+            ui_state.resource_packs.remove(i);
+        }
+    });
+
+    if all {
+        render_all(&mut jobs, &mut contexts, &mut ui_state);
     } else if optimize {
-        optimize_tiles(
-            commands,
-            ui_state.save_name.clone(),
-            tiles,
-            thread_pool,
-            ui_state,
-        );
+        optimize_tiles(commands, sprites, &mut tiles, &mut jobs, &mut ui_state);
     }
 }
 
-fn render_all(
-    mut commands: Commands,
-    thread_pool: Res<AsyncComputeTaskPool>,
-    mut ui_state: ResMut<UIState>,
-) {
-    let mut path = ui_state.save_path.clone();
-    path.push_str("\\region");
-    let dir = fs::read_dir(path).unwrap();
-    let regions = dir
-        .map(|f| f.as_ref().unwrap().path())
-        .collect::<Vec<PathBuf>>();
-    let texture_cache = Arc::new(Mutex::new(HashMap::new()));
-
-    for region in regions {
-        let reg = Region::from_file(region.clone().to_str().unwrap().into());
-        for x in 0..32 {
-            for z in 0..32 {
-                let region_file_name = reg.filename.clone();
-                let region_path = region.clone();
-                let chunk = reg.get_chunk(x, z);
-                let cache = texture_cache.clone();
-                let s_name = ui_state.save_name.clone();
-                match chunk {
-                    Some(c) => {
-                        if c.get_status() == "full" {
-                            let task = thread_pool.spawn(async move {
-                                Some(render_chunk(
-                                    c,
-                                    region_file_name,
-                                    region_path,
-                                    s_name,
-                                    cache,
-                                ))
-                            });
-                            commands.spawn().insert(task);
-                            ui_state.rendering_count += 1;
-                        }
-                    }
-                    None => (),
-                }
+// The world, tile set, resource packs, vanilla asset version, proto chunk setting and render mode a context was
+// built for
+type ContextKey = (
+    String,
+    String,
+    Vec<String>,
+    Option<String>,
+    bool,
+    RenderMode,
+);
+
+// Jobs share the assets and texture cache of one render context for as long as nothing it was built from changes,
+// so pack zips are only opened once and textures stay warm from one job to the next
+#[derive(Default)]
+pub struct RenderContexts(Option<(ContextKey, RenderContext)>);
+
+impl RenderContexts {
+    // A context for a new job, with its own diagnostics and nothing read from the world yet
+    pub fn get(&mut self, ui_state: &mut UIState) -> Result<Arc<RenderContext>, RenderError> {
+        let key = context_key(ui_state);
+        if let Some((built_for, ctx)) = &self.0 {
+            if *built_for == key {
+                return Ok(Arc::new(ctx.for_job()));
             }
         }
+        self.0 = None;
+        let ctx = RenderContext::new(&key.0, &key.1, &key.2, key.4, key.5)?;
+        ui_state.pack_errors = ctx.assets.invalid_packs().to_vec();
+        let job_ctx = Arc::new(ctx.for_job());
+        self.0 = Some((key, ctx));
+        Ok(job_ctx)
     }
-}
 
-fn determine_chunks(
-    mut commands: Commands,
-    thread_pool: Res<AsyncComputeTaskPool>,
-    mut transforms: ParamSet<(Query<&mut Transform, With<Camera2d>>, Query<&Transform>)>,
-    windows: Res<Windows>,
-    mut ui_state: ResMut<UIState>,
-) {
-    for transform in transforms.p0().iter() {
-        let loc = transform.translation;
-        let window = windows.get_primary().unwrap();
-        let (window_width, window_height) = (window.width(), window.height());
-
-        let chunks_width =
-            (window_width / (16.0 * 16.0) * ui_state.zoom_enumerated() as f32).ceil();
-        let chunks_height =
-            (window_height / (16.0 * 16.0) * ui_state.zoom_enumerated() as f32).ceil();
-        let loc_chunks = (loc.x / (16.0 * 16.0), -loc.y / (16.0 * 16.0));
-        let mut chunks = Vec::new();
-        for x in (loc_chunks.0 - chunks_width) as i32 - 1
-            ..(loc_chunks.0 + (chunks_width / 2.0)) as i32 + 1
-        {
-            for y in (loc_chunks.1 - chunks_height) as i32 - 1
-                ..(loc_chunks.1 + (chunks_height / 2.0)) as i32 + 1
-            {
-                chunks.push((x, y));
+    // Keeps using a running job's context while nothing it was built from changed, a new one otherwise
+    pub fn reuse(
+        &mut self,
+        ui_state: &mut UIState,
+        running: Option<Arc<RenderContext>>,
+    ) -> Result<Arc<RenderContext>, RenderError> {
+        if let (Some(running), Some((built_for, ctx))) = (&running, &self.0) {
+            if *built_for == context_key(ui_state) && Arc::ptr_eq(&running.assets, &ctx.assets) {
+                return Ok(running.clone());
             }
         }
-        ui_state.rendering_count += chunks.len() as u32;
-        let texture_cache = Arc::new(Mutex::new(HashMap::new()));
-        for chunk_coords in chunks {
-            let mut path = ui_state.save_path.clone();
-            let mut save_path = std::env::current_dir().unwrap();
-            save_path.push("saves");
-            save_path.push(format!("{}", ui_state.save_name));
-            let s_name = ui_state.save_name.clone();
-            let cache = texture_cache.clone();
-            let task = thread_pool.spawn(async move {
-                let region_coords = (
-                    (chunk_coords.0 as f32 / 32 as f32).floor(),
-                    (chunk_coords.1 as f32 / 32 as f32).floor(),
-                );
-                path.push_str("\\region");
-                let dir = fs::read_dir(path).unwrap();
-                match dir
-                    .filter(|f| {
-                        f.as_ref().unwrap().path().file_name().unwrap()
-                            == format!("r.{}.{}.mca", region_coords.0, region_coords.1).as_str()
-                    })
-                    .map(|f| f.as_ref().unwrap().path())
-                    .collect::<Vec<PathBuf>>()
-                    .first()
-                {
-                    Some(region_path) => {
-                        let region = Region::from_file(region_path.to_str().unwrap().into());
-                        match region.get_chunk(
-                            ((32 + chunk_coords.0) % 32) as u32,
-                            ((32 + chunk_coords.1) % 32) as u32,
-                        ) {
-                            Some(chunk) => {
-                                if chunk.get_status() == "full" {
-                                    save_path.push(
-                                        &format!("r.{}.{}", region_coords.0, region_coords.1)
-                                            .to_string(),
-                                    );
-                                    if save_path.exists() {
-                                        let rendered_dir = fs::read_dir(save_path).unwrap();
-                                        let rendered_chunk = rendered_dir
-                                            .filter(|f| {
-                                                f.as_ref()
-                                                    .unwrap()
-                                                    .file_name()
-                                                    .to_str()
-                                                    .unwrap()
-                                                    .contains(
-                                                        format!(
-                                                            "chunk{}.{}",
-                                                            ((32 + chunk_coords.0) % 32),
-                                                            ((32 + chunk_coords.1) % 32)
-                                                        )
-                                                        .as_str(),
-                                                    )
-                                            })
-                                            .map(|f| f.unwrap())
-                                            .collect::<Vec<DirEntry>>();
-                                        if rendered_chunk.len() > 0 {
-                                            // Check if the chunk is already in an existing entity
-                                            // let ent = &existing_tiles.iter().any(|(e, i)| i.coords.0 == chunk_coords.0 as u32 && i.coords.1 == chunk_coords.1 as u32);
-                                            // Exists a rendered image
-                                            if rendered_chunk
-                                                .first()
-                                                .unwrap()
-                                                .file_name()
-                                                .to_str()
-                                                .unwrap()
-                                                .split(".")
-                                                .collect::<Vec<&str>>()
-                                                .get(2)
-                                                .unwrap()
-                                                .to_string()
-                                                .parse::<i64>()
-                                                .unwrap()
-                                                >= *chunk.get_last_update()
-                                            {
-                                                let content = rendered_chunk.first();
-                                                let path = match content {
-                                                    Some(entry) => match entry.path().to_str() {
-                                                        Some(entry_path) => entry_path.to_string(),
-                                                        None => todo!(),
-                                                    },
-                                                    None => todo!(),
-                                                };
-                                                return Some(path);
-                                            }
-                                        }
-                                    }
-
-                                    Some(render_chunk(
-                                        chunk,
-                                        region.filename,
-                                        region_path.to_path_buf(),
-                                        s_name,
-                                        cache,
-                                    ))
-                                } else {
-                                    None // Chunk not fully rendered
-                                }
-                            }
-                            None => None, // Chunk does not exist
-                        }
-                    }
-                    None => None, // Region file does not exist
-                }
-            });
-            commands.spawn().insert(task);
+        self.get(ui_state)
+    }
+}
+
+fn context_key(ui_state: &UIState) -> ContextKey {
+    (
+        ui_state.save_path.clone(),
+        ui_state.tile_set(),
+        ui_state.resource_packs.clone(),
+        ui_state.asset_version.clone(),
+        ui_state.render_proto_chunks,
+        ui_state.render_mode,
+    )
+}
+
+fn render_all(jobs: &mut JobManager, contexts: &mut RenderContexts, ui_state: &mut UIState) {
+    let ctx = match contexts.get(ui_state) {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            ui_state.failed_chunks.push(e.to_string());
+            return;
+        }
+    };
+    let regions = match ctx.world.regions() {
+        Ok(regions) => regions,
+        Err(e) => {
+            ui_state.failed_chunks.push(e.to_string());
+            return;
+        }
+    };
+    let mut chunks = Vec::new();
+    for (region_x, region_z) in regions {
+        for x in 0..32 {
+            for z in 0..32 {
+                chunks.push(WorkItem::Chunk((region_x * 32 + x, region_z * 32 + z)));
+            }
         }
     }
+    jobs.submit(
+        "Render All".to_string(),
+        Priority::Background,
+        Some(ctx),
+        chunks,
+    );
 }
 
 fn grab_mouse(
@@ -360,8 +413,8 @@ fn grab_mouse(
             let delta = event.delta;
             for (mut transform, _) in cameras.iter_mut() {
                 transform.translation = Vec3::new(
-                    transform.translation.x - delta.x * ui_state.zoom_enumerated() as f32,
-                    transform.translation.y + delta.y * ui_state.zoom_enumerated() as f32,
+                    transform.translation.x - delta.x * ui_state.zoom_scale(),
+                    transform.translation.y + delta.y * ui_state.zoom_scale(),
                     transform.translation.z,
                 );
             }
@@ -372,138 +425,240 @@ fn grab_mouse(
 fn zoom(
     mut mouse_wheel_events: EventReader<MouseWheel>,
     mut ui_state: ResMut<UIState>,
-    mut projections: Query<(&mut OrthographicProjection, With<Camera2d>)>,
+    windows: Res<Windows>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera2d>>,
 ) {
+    let window = windows.get_primary().unwrap();
     for event in mouse_wheel_events.iter() {
-        let zoom = if event.y == 1.0 {
-            ui_state.zoom_in()
-        } else if event.y == -1.0 {
-            ui_state.zoom_out()
-        } else {
-            false
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_LINE,
         };
-        if zoom {
-            for (mut projection, _) in projections.iter_mut() {
-                let zoom_scale = ui_state.zoom_enumerated() as f32;
-                projection.scale = zoom_scale;
-            }
-            ui_state.viewport_moved = true;
+        let old_scale = ui_state.zoom_scale();
+        if !ui_state.zoom_by(ZOOM_STEP.powf(-lines)) {
+            continue;
         }
+        let new_scale = ui_state.zoom_scale();
+        // Zoom around the cursor, the block under it stays in place
+        let cursor = window
+            .cursor_position()
+            .map(|c| c - Vec2::new(window.width(), window.height()) / 2.0)
+            .unwrap_or(Vec2::ZERO);
+        for (mut transform, mut projection) in cameras.iter_mut() {
+            let offset = cursor * (old_scale - new_scale);
+            transform.translation.x += offset.x;
+            transform.translation.y += offset.y;
+            projection.scale = new_scale;
+        }
+        ui_state.viewport_moved = true;
     }
 }
 
-fn handle_per_chunk_images<'b>(
+fn handle_per_chunk_images(
     mut commands: Commands,
-    mut transform_tasks: Query<(Entity, &mut Task<Option<String>>)>,
+    mut transform_tasks: Query<(Entity, &mut Task<ChunkResult>, &JobTask)>,
     asset_server: Res<AssetServer>,
     mut ui_state: ResMut<UIState>,
+    mut jobs: ResMut<JobManager>,
+    mut tiles: ResMut<TileManager>,
 ) {
-    for (entity, mut task) in transform_tasks.iter_mut() {
-        if let Some(path) = future::block_on(futures_lite::future::poll_once(&mut *task)) {
-            match path {
-                Some(path_str) => {
-                    let parts = path_str
-                        .split("\\")
-                        .collect::<Vec<&str>>()
-                        .last()
-                        .unwrap()
-                        .split("chunk")
-                        .collect::<Vec<&str>>()
-                        .last()
-                        .unwrap()
-                        .split(".")
-                        .collect::<Vec<&str>>();
-                    let region = path_str.split("\\").collect::<Vec<&str>>();
-                    let region_parts = region[region.len() - 2].split(".").collect::<Vec<&str>>();
-                    let region_x = region_parts[1].parse::<f32>().unwrap();
-                    let region_z = region_parts[2].parse::<f32>().unwrap();
-                    let x = parts[0].parse::<f32>().unwrap();
-                    let z = parts[1].parse::<f32>().unwrap();
-                    commands
-                        .spawn_bundle(SpriteBundle {
-                            texture: asset_server
-                                .load(&path_str.split("saves").collect::<Vec<&str>>()[1][1..]),
-
-                            transform: Transform::from_xyz(
-                                x * 256.0 + 8192.0 * region_x + 128.0,
-                                (z * 256.0 + 8192.0 * region_z) * -1.0 - 128.0,
-                                1.0,
-                            ),
-                            ..default()
-                        })
-                        .insert(ChunkSpriteCoords((x as u32, z as u32)))
-                        .insert(ChunkSpriteRegionCoords((region_x as i32, region_z as i32)));
+    for (entity, mut task, job) in transform_tasks.iter_mut() {
+        if let Some(((chunk_x, chunk_z), result)) =
+            future::block_on(futures_lite::future::poll_once(&mut *task))
+        {
+            let success = !matches!(result, Some(Err(_)));
+            let texture = match result {
+                Some(Ok(path_str)) => {
+                    Some(asset_server.load(&path_str.split("saves").collect::<Vec<&str>>()[1][1..]))
                 }
-                None => (), //println!("Unavailable chunk requested"),
+                Some(Err(e)) => {
+                    ui_state
+                        .failed_chunks
+                        .push(format!("chunk {} {}: {}", chunk_x, chunk_z, e));
+                    Some(asset_server.load(ERROR_TILE))
+                }
+                None => None, //println!("Unavailable chunk requested"),
+            };
+            let sprite = texture.map(|texture| {
+                commands
+                    .spawn_bundle(SpriteBundle {
+                        texture,
+                        // High resolution packs produce larger tiles, a chunk always covers the same area
+                        sprite: Sprite {
+                            custom_size: Some(Vec2::splat(CHUNK_SIZE)),
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(
+                            chunk_x as f32 * CHUNK_SIZE + CHUNK_SIZE / 2.0,
+                            chunk_z as f32 * -CHUNK_SIZE - CHUNK_SIZE / 2.0,
+                            1.0,
+                        ),
+                        ..default()
+                    })
+                    .id()
+            });
+            // Render All can finish a chunk the viewport already shows
+            if let Some(old) = tiles.insert(Lod::Chunk, (chunk_x, chunk_z), sprite) {
+                commands.entity(old).despawn();
             }
-            ui_state.rendering_count -= 1;
-            if ui_state.rendering_count == 0 {
-                ui_state.loading = false;
+            // Viewport streaming finishes a job on every move, only jobs the user started get a report
+            let explicit = matches!(jobs.get(job.0), Some(j) if j.priority == Priority::Background);
+            if let Some(ctx) = jobs.complete(job.0, success) {
+                if explicit {
+                    finish_render_job(&ctx, &mut ui_state);
+                }
             }
-            commands.entity(entity).remove::<Task<Option<String>>>();
+            commands.entity(entity).despawn();
         }
     }
 }
 
+// Summarises everything the renderer could not resolve once the last chunk of a job is done
+fn finish_render_job(ctx: &RenderContext, ui_state: &mut UIState) {
+    let report = ctx
+        .diagnostics
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .report();
+    let path = format!("saves\\{}\\render_report.txt", ctx.save_name);
+    if let Err(e) = fs::write(&path, &report) {
+        ui_state
+            .failed_chunks
+            .push(format!("Could not write {}: {}", path, e));
+    }
+    ui_state.render_report = Some(report);
+}
+
 fn handle_per_region_images(
     mut commands: Commands,
-    mut transform_tasks: Query<(Entity, &mut Task<String>)>,
+    mut transform_tasks: Query<(Entity, &mut Task<RegionResult>, &JobTask)>,
     asset_server: Res<AssetServer>,
     mut ui_state: ResMut<UIState>,
+    mut jobs: ResMut<JobManager>,
+    mut tiles: ResMut<TileManager>,
 ) {
-    for (entity, mut task) in transform_tasks.iter_mut() {
-        if let Some(path) = future::block_on(futures_lite::future::poll_once(&mut *task)) {
+    for (entity, mut task, job) in transform_tasks.iter_mut() {
+        if let Some(result) = future::block_on(futures_lite::future::poll_once(&mut *task)) {
+            commands.entity(entity).despawn();
+            jobs.complete(job.0, result.is_ok());
+            let (path, size) = match result {
+                Ok(image) => image,
+                Err(e) => {
+                    ui_state.failed_chunks.push(e.to_string());
+                    continue;
+                }
+            };
             let parts = path.split("\\").collect::<Vec<&str>>();
-            let region = parts[parts.len() - 2];
-            let region_parts = region.split(".").collect::<Vec<&str>>();
-            let region_x = region_parts[1].parse::<i32>().unwrap();
-            let region_z = region_parts[2].parse::<i32>().unwrap();
-            commands.spawn_bundle(SpriteBundle {
-                texture: asset_server.load(&format!(
-                    "{}\\saves{}",
-                    std::env::current_dir()
-                        .unwrap()
-                        .to_path_buf()
-                        .to_str()
-                        .unwrap(),
-                    &path[5..]
-                )),
-                transform: Transform::from_xyz(
-                    region_x as f32 * 8192.0 + (8192.0 / 2.0),
-                    (8192.0 * region_z as f32) * -1.0 - (8192.0 / 2.0),
-                    1.0,
-                ),
-                ..default()
-            });
-            commands.entity(entity).remove::<Task<String>>();
-            ui_state.rendering_count -= 1;
+            let region = match world::region_coords_from_name(parts[parts.len() - 2]) {
+                Some(coords) => coords,
+                None => {
+                    ui_state
+                        .failed_chunks
+                        .push(RenderError::InvalidTileName(path).to_string());
+                    continue;
+                }
+            };
+            let sprite = spawn_region_sprite(&mut commands, &asset_server, &path, region);
+            if let Some(old) = tiles.insert(Lod::Region(size), region, Some(sprite)) {
+                commands.entity(old).despawn();
+            }
         }
     }
 }
 
-fn drag_folder(mut events: EventReader<FileDragAndDrop>, mut ui_state: ResMut<UIState>) {
+fn handle_asset_extraction(
+    mut commands: Commands,
+    mut extraction_tasks: Query<(Entity, &mut Task<Result<String, String>>)>,
+    mut ui_state: ResMut<UIState>,
+) {
+    for (entity, mut task) in extraction_tasks.iter_mut() {
+        if let Some(result) = future::block_on(future::poll_once(&mut *task)) {
+            match result {
+                Ok(version) => ui_state.asset_version = Some(version),
+                Err(e) => ui_state
+                    .failed_chunks
+                    .push(format!("Failed to extract assets: {}", e)),
+            }
+            ui_state.extracting_assets = false;
+            commands
+                .entity(entity)
+                .remove::<Task<Result<String, String>>>();
+        }
+    }
+}
+
+pub fn extract_assets(
+    commands: &mut Commands,
+    thread_pool: &AsyncComputeTaskPool,
+    ui_state: &mut UIState,
+    jar: PathBuf,
+) {
+    if ui_state.extracting_assets {
+        return;
+    }
+    ui_state.extracting_assets = true;
+    let task = thread_pool
+        .spawn(async move { assets::extract_client_jar(&jar).map_err(|e| e.to_string()) });
+    commands.spawn().insert(task);
+}
+
+// Reads the level.dat of a newly opened world and looks for the launcher's client jar of the world's version, which
+// the world info window offers to extract
+fn open_level(ui_state: &mut UIState) {
+    let level = Level::read(&ui_state.save_path).map_err(|e| e.to_string());
+    ui_state.launcher_jar = level
+        .as_ref()
+        .ok()
+        .and_then(|l| l.version_name.as_deref())
+        .and_then(assets::launcher_jar);
+    ui_state.level = Some(level);
+}
+
+fn drag_folder(
+    mut commands: Commands,
+    mut events: EventReader<FileDragAndDrop>,
+    mut ui_state: ResMut<UIState>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+) {
     for event in events.iter() {
-        match event {
-            // Only care about dropped files
-            FileDragAndDrop::DroppedFile { id: _, path_buf } => {
-                // Only care about directories
-                if path_buf.is_dir() {
-                    ui_state.save_name =
-                        path_buf.file_name().unwrap().to_str().unwrap().to_string();
-                    // Make sure directory contains a region folder
-                    let region = path_buf.join("region");
-                    if region.exists() {
-                        let mut region_dir = fs::read_dir(region).unwrap();
-                        if region_dir
-                            .any(|f| f.as_ref().unwrap().path().extension().unwrap() == "mca")
-                        {
-                            ui_state.save_path = path_buf.to_str().unwrap().into();
-                        }
-                    }
+        // Only care about dropped files, hovering and cancelling are ignored
+        if let FileDragAndDrop::DroppedFile { id: _, path_buf } = event {
+            // Paths are kept as strings in the ui state and tile names
+            let path = match path_buf.to_str() {
+                Some(path) => path.to_string(),
+                None => {
+                    ui_state.failed_chunks.push(format!(
+                        "Cannot open {}, the path is not valid unicode",
+                        path_buf.display()
+                    ));
+                    continue;
+                }
+            };
+            if let Some(jar) = assets::find_client_jar(path_buf) {
+                extract_assets(&mut commands, &thread_pool, &mut ui_state, jar);
+            } else if assets::is_resource_pack(path_buf) {
+                if !ui_state.resource_packs.contains(&path) {
+                    ui_state.resource_packs.insert(0, path);
+                }
+            // Only care about directories
+            } else if path_buf.is_dir() {
+                let name = match path_buf.file_name().and_then(|name| name.to_str()) {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+                ui_state.save_name = name;
+                // Make sure directory contains a region folder with at least one region file
+                let has_regions = match fs::read_dir(path_buf.join("region")) {
+                    Ok(region_dir) => region_dir
+                        .filter_map(|f| f.ok())
+                        .any(|f| f.path().extension() == Some(OsStr::new("mca"))),
+                    Err(_) => false,
+                };
+                if has_regions {
+                    ui_state.save_path = path;
+                    open_level(&mut ui_state);
                 }
             }
-            // Hover and Cancel dragged files
-            _ => (),
         }
     }
 }
@@ -512,78 +667,42 @@ fn drag_folder(mut events: EventReader<FileDragAndDrop>, mut ui_state: ResMut<UI
 // A smaller number of larger tiles, four tiles per region? 16x16 chunks = 16x16x16 pixels per side
 fn optimize_tiles(
     mut commands: Commands,
-    save_name: String,
-    tiles: Query<(Entity, With<Sprite>)>,
-    thread_pool: Res<AsyncComputeTaskPool>,
-    mut ui_state: ResMut<UIState>,
+    sprites: Query<Entity, (With<Sprite>, Without<MarkerSprite>)>,
+    tiles: &mut TileManager,
+    jobs: &mut JobManager,
+    ui_state: &mut UIState,
 ) {
-    let mut dir = std::env::current_dir().unwrap();
+    let mut dir = match std::env::current_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            ui_state.failed_chunks.push(e.to_string());
+            return;
+        }
+    };
     dir.push("saves\\");
-    dir.push(save_name.clone());
-    for (e, _) in tiles.iter() {
+    dir.push(ui_state.tile_set());
+    for e in sprites.iter() {
         commands.entity(e).despawn();
     }
-    for region_folder in dir.read_dir().unwrap().map(|f| f.unwrap()) {
-        let save_name = save_name.clone();
-        ui_state.rendering_count += 1;
-        let task = thread_pool.spawn(async move {
-            let mut img = RgbaImage::new(512 * 16, 512 * 16);
-            for x in 0..32 {
-                for z in 0..32 {
-                    let region_dir = region_folder.path().read_dir().unwrap();
-                    let chunk = region_dir
-                        .filter(|f| {
-                            f.as_ref()
-                                .unwrap()
-                                .file_name()
-                                .to_str()
-                                .unwrap()
-                                .contains(format!("chunk{}.{}", x, z).as_str())
-                        })
-                        .map(|f| f.unwrap())
-                        .collect::<Vec<DirEntry>>();
-                    if chunk.len() > 0 {
-                        // There exists a chunk
-                        let chunk_img_d = open(chunk[0].path()).unwrap();
-                        let chunk_img = chunk_img_d.as_rgba8().unwrap();
-                        for cx in 0..chunk_img.dimensions().0 {
-                            for cz in 0..chunk_img.dimensions().1 {
-                                img.put_pixel(
-                                    x * 16 * 16 + cx,
-                                    z * 16 * 16 + cz,
-                                    *chunk_img.get_pixel(cx, cz),
-                                );
-                            }
-                        }
-
-                        // load file and place chunk into larger image
-                    } else {
-                        // Nah cuh
-                        // place blank chunk into larger image, consider a checkerboard pattern
-                        for cx in 0..256 {
-                            for cz in 0..256 {
-                                let pixel = if ((cx / 16) % 2 == 0 && (cz / 16) % 2 == 1)
-                                    || ((cx / 16) % 2 == 1 && (cz / 16) % 2 == 0)
-                                {
-                                    Rgba::from([100, 100, 100, 255])
-                                } else {
-                                    Rgba::from([150, 150, 150, 255])
-                                };
-                                img.put_pixel(x * 16 * 16 + cx, z * 16 * 16 + cz, pixel);
-                            }
-                        }
-                    }
-                }
-            }
-            let img_path = format!(
-                "saves\\{}\\{}\\{}.png",
-                save_name,
-                region_folder.file_name().to_str().unwrap(),
-                region_folder.file_name().to_str().unwrap()
-            );
-            img.save(&img_path).unwrap();
-            img_path
-        });
-        commands.spawn().insert(task);
-    }
+    tiles.clear();
+    let region_folders = match dir.read_dir() {
+        Ok(entries) => entries
+            .filter_map(|f| f.ok())
+            .map(|f| f.path())
+            .filter(|p| p.is_dir())
+            .map(|folder| WorkItem::Region(folder, REGION_IMAGE_SIZES[0]))
+            .collect(),
+        Err(e) => {
+            ui_state
+                .failed_chunks
+                .push(RenderError::from(e).to_string());
+            return;
+        }
+    };
+    jobs.submit(
+        "Optimize Tiles".to_string(),
+        Priority::Background,
+        None,
+        region_folders,
+    );
 }
@@ -0,0 +1,502 @@
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    prelude::*,
+    render::camera::Camera2d,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use bevy_egui::{egui, EguiContext};
+use futures_lite::future;
+use nbt::Blob;
+
+use crate::{
+    anvil,
+    inspector::{Inspector, BLOCK_SIZE},
+    navigation::centre_on,
+    UIState,
+};
+
+mod block_entities;
+mod chunks;
+mod entities;
+mod players;
+mod poi;
+mod structures;
+
+pub const OVERWORLD: &str = "minecraft:overworld";
+// Folder of each dimension inside a world
+pub const DIMENSIONS: [(&str, &str); 3] = [
+    ("", OVERWORLD),
+    ("DIM-1", "minecraft:the_nether"),
+    ("DIM1", "minecraft:the_end"),
+];
+// Markers stay the same size on screen at every zoom level
+const MARKER_SIZE: f32 = 10.0;
+// Labels are painted next to markers once a block is at least this many pixels wide, further out they pile up
+const LABEL_BLOCK_PIXELS: f32 = 8.0;
+// Listing every marker of a large world would only slow the window down
+const MAX_LISTED: usize = 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Layer {
+    Players,
+    Respawns,
+    Structures,
+    PointsOfInterest,
+    Signs,
+    Banners,
+    Lodestones,
+    Entities,
+    ItemFrames,
+    Paintings,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 10] = [
+        Layer::Players,
+        Layer::Respawns,
+        Layer::Structures,
+        Layer::PointsOfInterest,
+        Layer::Signs,
+        Layer::Banners,
+        Layer::Lodestones,
+        Layer::Entities,
+        Layer::ItemFrames,
+        Layer::Paintings,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Layer::Players => "Players",
+            Layer::Respawns => "Respawn points",
+            Layer::Structures => "Structures",
+            Layer::PointsOfInterest => "Points of interest",
+            Layer::Signs => "Signs",
+            Layer::Banners => "Banners",
+            Layer::Lodestones => "Lodestones",
+            Layer::Entities => "Entities",
+            Layer::ItemFrames => "Item frames",
+            Layer::Paintings => "Paintings",
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            Layer::Players => Color::rgb(0.2, 0.6, 1.0),
+            Layer::Respawns => Color::rgb(1.0, 0.3, 0.8),
+            Layer::Structures => Color::rgb(1.0, 0.8, 0.1),
+            Layer::PointsOfInterest => Color::rgb(0.3, 0.9, 0.3),
+            Layer::Signs => Color::rgb(0.8, 0.6, 0.3),
+            Layer::Banners => Color::rgb(0.9, 0.1, 0.1),
+            Layer::Lodestones => Color::rgb(0.6, 0.6, 0.7),
+            Layer::Entities => Color::rgb(0.3, 0.8, 0.4),
+            Layer::ItemFrames => Color::rgb(0.7, 0.5, 0.3),
+            Layer::Paintings => Color::rgb(0.9, 0.6, 0.2),
+        }
+    }
+}
+
+pub struct Marker {
+    pub layer: Layer,
+    pub label: String,
+    pub position: (i32, i32, i32),
+    pub dimension: String,
+    pub details: Vec<String>,
+    // Block area covered as min x, min z, max x, max z
+    pub bounds: Option<[i32; 4]>,
+    // Texture drawn in place of the coloured square, relative to the `saves` folder the asset server loads from
+    pub icon: Option<String>,
+    pub picture: Option<Picture>,
+}
+
+// A texture laid on the map to scale, sizes and positions are in blocks
+pub struct Picture {
+    // Relative to the `saves` folder the asset server loads from
+    pub path: String,
+    pub centre: (f32, f32),
+    pub size: (f32, f32),
+    pub rotation: f32,
+}
+
+impl Marker {
+    fn covers(&self, (x, z): (i32, i32), reach: i32) -> bool {
+        let near = (self.position.0 - x).abs() <= reach && (self.position.2 - z).abs() <= reach;
+        near || matches!(self.bounds, Some([min_x, min_z, max_x, max_z])
+            if x >= min_x && x <= max_x && z >= min_z && z <= max_z)
+    }
+}
+
+// Everything a loader found in a world and every file it could not read, one bad file does not hide the rest
+type LoadResult = (Vec<Marker>, Vec<String>);
+
+// Each loader reads one kind of marker from a world directory
+fn loaders() -> Vec<fn(&Path) -> LoadResult> {
+    vec![players::load, chunks::load, poi::load, entities::load]
+}
+
+// Every region format file in a folder of a world
+pub fn region_files(dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension() == Some(OsStr::new("mca")))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Every chunk of a region format file that could be read. The file or chunks that could not are added to
+// `errors`, once per file so a damaged region does not flood the list.
+pub fn read_chunks(path: &Path, errors: &mut Vec<String>) -> Vec<Blob> {
+    let chunks = match anvil::read_chunks(path) {
+        Ok(chunks) => chunks,
+        Err(e) => {
+            errors.push(format!("Could not read {}: {}", path.display(), e));
+            return Vec::new();
+        }
+    };
+    let mut read = Vec::new();
+    let (mut failed, mut first) = (0, None);
+    for ((x, z), chunk) in chunks {
+        match chunk {
+            Ok(chunk) => read.push(chunk),
+            Err(e) => {
+                failed += 1;
+                first.get_or_insert(format!("{} {}: {}", x, z, e));
+            }
+        }
+    }
+    if let Some(first) = first {
+        errors.push(format!(
+            "Could not read {} chunks of {}, first at {}",
+            failed,
+            path.display(),
+            first
+        ));
+    }
+    read
+}
+
+// Strips the namespace from an id for display
+pub fn short_id(id: &str) -> &str {
+    id.strip_prefix("minecraft:").unwrap_or(id)
+}
+
+#[derive(Component)]
+pub struct MarkerSprite;
+
+// Structure areas and pictures, drawn to scale rather than at a fixed size on screen
+#[derive(Component)]
+pub struct ToScale;
+
+#[derive(Default)]
+pub struct Markers {
+    markers: Vec<Marker>,
+    hidden: HashSet<Layer>,
+    errors: Vec<String>,
+    // The world the markers were loaded from
+    save_path: String,
+    // Set when the sprites no longer match the markers
+    dirty: bool,
+    query: String,
+}
+
+impl Markers {
+    fn visible(&self) -> impl Iterator<Item = &Marker> {
+        self.markers
+            .iter()
+            .filter(|m| !self.hidden.contains(&m.layer))
+    }
+
+    // Visible markers with the search text in their label or details
+    fn search(&self) -> impl Iterator<Item = &Marker> {
+        let query = self.query.trim().to_lowercase();
+        self.visible().filter(move |m| {
+            query.is_empty()
+                || m.label.to_lowercase().contains(&query)
+                || m.details.iter().any(|d| d.to_lowercase().contains(&query))
+        })
+    }
+
+    fn count(&self, layer: Layer) -> usize {
+        self.markers.iter().filter(|m| m.layer == layer).count()
+    }
+}
+
+// Starts reading markers in the background whenever a different world is opened
+pub fn load_markers(
+    mut commands: Commands,
+    mut markers: ResMut<Markers>,
+    ui_state: Res<UIState>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+) {
+    if ui_state.save_path.is_empty() || markers.save_path == ui_state.save_path {
+        return;
+    }
+    markers.save_path = ui_state.save_path.clone();
+    markers.markers.clear();
+    markers.errors.clear();
+    markers.dirty = true;
+    for loader in loaders() {
+        let save_path = ui_state.save_path.clone();
+        let task: Task<(String, LoadResult)> =
+            thread_pool.spawn(async move { (save_path.clone(), loader(Path::new(&save_path))) });
+        commands.spawn().insert(task);
+    }
+}
+
+pub fn handle_marker_loads(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut Task<(String, LoadResult)>)>,
+    mut markers: ResMut<Markers>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        if let Some((save_path, result)) = future::block_on(future::poll_once(&mut *task)) {
+            // Results for a world that has since been closed are dropped
+            if save_path == markers.save_path {
+                let (loaded, errors) = result;
+                markers.markers.extend(loaded);
+                markers.errors.extend(errors);
+                markers.dirty = true;
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Respawns the marker sprites when markers or layers change and keeps them a constant size on screen
+pub fn sync_marker_sprites(
+    mut commands: Commands,
+    mut markers: ResMut<Markers>,
+    asset_server: Res<AssetServer>,
+    mut sprites: Query<(Entity, &mut Transform, Option<&ToScale>), With<MarkerSprite>>,
+    projections: Query<&OrthographicProjection, With<Camera2d>>,
+) {
+    let scale = projections.get_single().map(|p| p.scale).unwrap_or(1.0);
+    if markers.dirty {
+        markers.dirty = false;
+        for (entity, _, _) in sprites.iter() {
+            commands.entity(entity).despawn();
+        }
+        // Only the overworld is drawn on the map, other dimensions are still listed
+        for marker in markers.visible().filter(|m| m.dimension == OVERWORLD) {
+            let (x, _, z) = marker.position;
+            if let Some([min_x, min_z, max_x, max_z]) = marker.bounds {
+                let size = Vec2::new(
+                    (max_x - min_x + 1) as f32 * BLOCK_SIZE,
+                    (max_z - min_z + 1) as f32 * BLOCK_SIZE,
+                );
+                commands
+                    .spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            color: *marker.layer.color().set_a(0.25),
+                            custom_size: Some(size),
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(
+                            min_x as f32 * BLOCK_SIZE + size.x / 2.0,
+                            -(min_z as f32 * BLOCK_SIZE) - size.y / 2.0,
+                            1.5,
+                        ),
+                        ..default()
+                    })
+                    .insert(MarkerSprite)
+                    .insert(ToScale);
+            }
+            // Pictures stand in for the marker itself
+            if let Some(picture) = &marker.picture {
+                let (centre_x, centre_z) = picture.centre;
+                commands
+                    .spawn_bundle(SpriteBundle {
+                        texture: asset_server.load(&picture.path),
+                        sprite: Sprite {
+                            custom_size: Some(
+                                Vec2::new(picture.size.0, picture.size.1) * BLOCK_SIZE,
+                            ),
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(
+                            centre_x * BLOCK_SIZE,
+                            -centre_z * BLOCK_SIZE,
+                            1.8,
+                        )
+                        .with_rotation(Quat::from_rotation_z(picture.rotation)),
+                        ..default()
+                    })
+                    .insert(MarkerSprite)
+                    .insert(ToScale);
+                continue;
+            }
+            let mut bundle = SpriteBundle {
+                sprite: Sprite {
+                    color: marker.layer.color(),
+                    custom_size: Some(Vec2::splat(MARKER_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    (x as f32 + 0.5) * BLOCK_SIZE,
+                    -(z as f32 + 0.5) * BLOCK_SIZE,
+                    2.0,
+                )
+                .with_scale(Vec3::splat(scale)),
+                ..default()
+            };
+            if let Some(icon) = &marker.icon {
+                bundle.texture = asset_server.load(icon);
+                bundle.sprite.color = Color::WHITE;
+            }
+            commands.spawn_bundle(bundle).insert(MarkerSprite);
+        }
+    } else {
+        for (_, mut transform, to_scale) in sprites.iter_mut() {
+            if to_scale.is_none() {
+                transform.scale = Vec3::splat(scale);
+            }
+        }
+    }
+}
+
+pub fn markers_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut markers: ResMut<Markers>,
+    mut ui_state: ResMut<UIState>,
+    inspector: Res<Inspector>,
+    mut cameras: Query<(&mut Transform, &OrthographicProjection), With<Camera2d>>,
+    windows: Res<Windows>,
+) {
+    if markers.save_path.is_empty() {
+        return;
+    }
+    let (mut camera, projection) = match cameras.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let mut target = None;
+    let mut toggled = None;
+    egui::Window::new("Markers").show(egui_context.ctx_mut(), |ui| {
+        for layer in Layer::ALL {
+            let mut shown = !markers.hidden.contains(&layer);
+            let count = markers.count(layer);
+            if ui
+                .checkbox(&mut shown, format!("{} ({})", layer.label(), count))
+                .changed()
+            {
+                toggled = Some(layer);
+            }
+        }
+        for error in &markers.errors {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Search");
+            let response = ui.text_edit_singleline(&mut markers.query);
+            // Enter jumps straight to the first hit on the map
+            if response.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+                target = markers
+                    .search()
+                    .find(|m| m.dimension == OVERWORLD)
+                    .map(|m| (m.position.0, m.position.2));
+            }
+        });
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for marker in markers.search().take(MAX_LISTED) {
+                    let (x, y, z) = marker.position;
+                    ui.horizontal(|ui| {
+                        let on_map = marker.dimension == OVERWORLD;
+                        if ui.add_enabled(on_map, egui::Button::new("Go")).clicked() {
+                            target = Some((x, z));
+                        }
+                        ui.label(format!("{} {} {} {}", marker.label, x, y, z));
+                        if !on_map {
+                            ui.weak(&marker.dimension);
+                        }
+                    });
+                }
+                let more = markers.search().count().saturating_sub(MAX_LISTED);
+                if more > 0 {
+                    ui.weak(format!("{} more", more));
+                }
+            });
+    });
+    if let Some(layer) = toggled {
+        if !markers.hidden.remove(&layer) {
+            markers.hidden.insert(layer);
+        }
+        markers.dirty = true;
+    }
+    if let Some(target) = target {
+        centre_on(&mut camera, target);
+        ui_state.viewport_moved = true;
+    }
+
+    // Labels next to the markers on the map once zoomed in
+    if BLOCK_SIZE / projection.scale >= LABEL_BLOCK_PIXELS {
+        let window = windows.get_primary().unwrap();
+        let painter = egui_context
+            .ctx_mut()
+            .layer_painter(egui::LayerId::background());
+        let screen = egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(window.width(), window.height()),
+        );
+        for marker in markers
+            .visible()
+            .filter(|m| m.dimension == OVERWORLD && !m.label.is_empty())
+        {
+            let (x, _, z) = marker.position;
+            let position = egui::pos2(
+                ((x as f32 + 0.5) * BLOCK_SIZE - camera.translation.x) / projection.scale
+                    + window.width() / 2.0,
+                ((z as f32 + 0.5) * BLOCK_SIZE + camera.translation.y) / projection.scale
+                    + window.height() / 2.0,
+            );
+            if screen.contains(position) {
+                painter.text(
+                    position + egui::vec2(MARKER_SIZE / 2.0 + 2.0, 0.0),
+                    egui::Align2::LEFT_CENTER,
+                    &marker.label,
+                    egui::FontId::proportional(12.0),
+                    egui::Color32::WHITE,
+                );
+            }
+        }
+    }
+
+    // Details of the marker under the cursor
+    let ctx = egui_context.ctx_mut();
+    if ctx.is_pointer_over_area() {
+        return;
+    }
+    let (cursor_x, cursor_z) = match inspector.cursor {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    let reach = (MARKER_SIZE / 2.0 * projection.scale / BLOCK_SIZE).ceil() as i32;
+    let hovered = markers
+        .visible()
+        .filter(|m| m.dimension == OVERWORLD)
+        .filter(|m| m.covers((cursor_x, cursor_z), reach))
+        .collect::<Vec<&Marker>>();
+    if hovered.is_empty() {
+        return;
+    }
+    egui::show_tooltip_at_pointer(ctx, egui::Id::new("marker_tooltip"), |ui| {
+        for marker in hovered {
+            let (x, y, z) = marker.position;
+            ui.strong(format!("{}: {}", marker.layer.label(), marker.label));
+            ui.label(format!("{} {} {}", x, y, z));
+            for detail in &marker.details {
+                ui.label(detail);
+            }
+        }
+    });
+}
@@ -0,0 +1,97 @@
+use nbt::{Blob, Value};
+use serde_json::Value as Json;
+
+use super::{Layer, Marker};
+use crate::tags::{compound, int, list, string, Compound};
+
+// Signs with text on them and banners that were renamed in an anvil
+pub fn from_chunk(chunk: &Blob, dimension: &str, markers: &mut Vec<Marker>) {
+    let block_entities = list(chunk, "block_entities").into_iter().flatten();
+    for entity in block_entities {
+        let entity = match entity {
+            Value::Compound(entity) => entity,
+            _ => continue,
+        };
+        let position = match (int(entity, "x"), int(entity, "y"), int(entity, "z")) {
+            (Some(x), Some(y), Some(z)) => (x, y, z),
+            _ => continue,
+        };
+        let id = string(entity, "id").unwrap_or_default();
+        let (layer, lines) = match id.as_str() {
+            "minecraft:sign" | "minecraft:hanging_sign" => (Layer::Signs, sign_text(entity)),
+            "minecraft:banner" => (
+                Layer::Banners,
+                string(entity, "CustomName")
+                    .map(|name| vec![text_component(&name)])
+                    .unwrap_or_default(),
+            ),
+            _ => continue,
+        };
+        let lines = lines
+            .into_iter()
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<String>>();
+        if lines.is_empty() {
+            continue;
+        }
+        markers.push(Marker {
+            layer,
+            label: lines.join(" "),
+            position,
+            dimension: dimension.to_string(),
+            details: lines,
+            bounds: None,
+            icon: None,
+            picture: None,
+        });
+    }
+}
+
+// Both sides of a 1.20 sign, or the four lines of an older one
+fn sign_text(sign: &Compound) -> Vec<String> {
+    let sides = ["front_text", "back_text"]
+        .iter()
+        .filter_map(|side| list(compound(sign, side)?, "messages"))
+        .flatten()
+        .filter_map(|message| match message {
+            Value::String(message) => Some(text_component(message)),
+            _ => None,
+        })
+        .collect::<Vec<String>>();
+    if !sides.is_empty() {
+        return sides;
+    }
+    ["Text1", "Text2", "Text3", "Text4"]
+        .iter()
+        .filter_map(|line| string(sign, line))
+        .map(|line| text_component(&line))
+        .collect()
+}
+
+// Plain text of a JSON text component, formatting is dropped
+fn text_component(json: &str) -> String {
+    match serde_json::from_str::<Json>(json) {
+        Ok(component) => flatten_component(&component),
+        // Some old signs hold plain text rather than JSON
+        Err(_) => json.to_string(),
+    }
+}
+
+fn flatten_component(component: &Json) -> String {
+    match component {
+        Json::String(text) => text.clone(),
+        Json::Array(parts) => parts.iter().map(flatten_component).collect(),
+        Json::Object(object) => {
+            let mut text = object
+                .get("text")
+                .and_then(|t| t.as_str())
+                .unwrap_or_default()
+                .to_string();
+            if let Some(extra) = object.get("extra") {
+                text.push_str(&flatten_component(extra));
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,26 @@
+use std::path::Path;
+
+use nbt::Blob;
+
+use super::{
+    block_entities, read_chunks, region_files, structures, LoadResult, Marker, DIMENSIONS,
+};
+
+// Everything that is found by looking through the NBT of every chunk, read in a single pass over the region files
+const EXTRACTORS: [fn(&Blob, &str, &mut Vec<Marker>); 2] =
+    [structures::from_chunk, block_entities::from_chunk];
+
+pub fn load(save_path: &Path) -> LoadResult {
+    let mut markers = Vec::new();
+    let mut errors = Vec::new();
+    for (folder, dimension) in DIMENSIONS {
+        for path in region_files(&save_path.join(folder).join("region")) {
+            for chunk in read_chunks(&path, &mut errors) {
+                for extract in EXTRACTORS {
+                    extract(&chunk, dimension, &mut markers);
+                }
+            }
+        }
+    }
+    (markers, errors)
+}
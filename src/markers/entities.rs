@@ -0,0 +1,233 @@
+use std::{collections::BTreeMap, f32::consts::PI, fs, path::Path};
+
+use image::{imageops, DynamicImage};
+use nbt::Value;
+
+use super::{read_chunks, region_files, short_id, Layer, LoadResult, Marker, Picture, DIMENSIONS};
+use crate::{
+    assets::Assets,
+    tags::{byte, compound, int_array, list, position, string, Compound},
+};
+
+const PAINTING: &str = "minecraft:painting";
+const ITEM_FRAMES: [&str; 2] = ["minecraft:item_frame", "minecraft:glow_item_frame"];
+const PICTURE_DIR: &str = "saves\\textures";
+
+// Since 1.17 entities are stored in their own region files, one marker is made per entity type in each chunk
+pub fn load(save_path: &Path) -> LoadResult {
+    // Pictures only use the vanilla assets, resource packs are applied when tiles are rendered
+    let assets = Assets::new(&[]);
+    clear_stale_pictures(assets.identity());
+    let mut markers = Vec::new();
+    let mut errors = Vec::new();
+    for (folder, dimension) in DIMENSIONS {
+        for path in region_files(&save_path.join(folder).join("entities")) {
+            for chunk in read_chunks(&path, &mut errors) {
+                let chunk_coords = match int_array(&chunk, "Position") {
+                    Some(pos) if pos.len() == 2 => (pos[0], pos[1]),
+                    _ => continue,
+                };
+                let mut by_type: BTreeMap<String, Vec<(i32, i32, i32)>> = BTreeMap::new();
+                for entity in list(&chunk, "Entities").into_iter().flatten() {
+                    let entity = match entity {
+                        Value::Compound(entity) => entity,
+                        _ => continue,
+                    };
+                    let (id, pos) = match string(entity, "id").zip(position(entity, "Pos")) {
+                        Some(found) => found,
+                        None => continue,
+                    };
+                    let block = (
+                        pos.0.floor() as i32,
+                        pos.1.floor() as i32,
+                        pos.2.floor() as i32,
+                    );
+                    if id == PAINTING {
+                        markers.push(painting(&assets, entity, pos, dimension, &mut errors));
+                    } else if ITEM_FRAMES.contains(&id.as_str()) {
+                        markers.push(item_frame(&assets, entity, block, dimension, &mut errors));
+                    } else {
+                        by_type.entry(id).or_default().push(block);
+                    }
+                }
+                let total = by_type.values().map(|e| e.len()).sum::<usize>();
+                for (id, positions) in by_type {
+                    let name = short_id(&id);
+                    let label = match positions.len() {
+                        1 => name.to_string(),
+                        count => format!("{} x{}", name, count),
+                    };
+                    let icon = entity_image(&assets, name).and_then(|image| {
+                        save_picture(&assets, "entity", name, image, &mut errors)
+                    });
+                    markers.push(Marker {
+                        layer: Layer::Entities,
+                        label,
+                        position: positions[0],
+                        dimension: dimension.to_string(),
+                        details: vec![format!(
+                            "{} entities in chunk {} {}",
+                            total, chunk_coords.0, chunk_coords.1
+                        )],
+                        bounds: None,
+                        icon,
+                        picture: None,
+                    });
+                }
+            }
+        }
+    }
+    (markers, errors)
+}
+
+// The mob's spawn egg, items like boats and minecarts use their item texture, older versions only have one
+// tinted egg for every mob so the face is cut out of the entity texture instead
+fn entity_image(assets: &Assets, name: &str) -> Option<DynamicImage> {
+    if let Some(image) = assets
+        .image(&format!("minecraft/textures/item/{}_spawn_egg.png", name))
+        .or_else(|| assets.image(&format!("minecraft/textures/item/{}.png", name)))
+    {
+        return Some(image);
+    }
+    let texture = assets
+        .image(&format!("minecraft/textures/entity/{}.png", name))
+        .or_else(|| assets.image(&format!("minecraft/textures/entity/{0}/{0}.png", name)))?;
+    // Most mobs share the player skin layout, an 8 pixel face at 8 8 of a 64 pixel wide texture
+    let scale = texture.width() / 64;
+    if scale == 0 {
+        return None;
+    }
+    Some(texture.crop_imm(8 * scale, 8 * scale, 8 * scale, 8 * scale))
+}
+
+// Paintings hang on walls, on the map they are laid flat with their top edge against the wall
+fn painting(
+    assets: &Assets,
+    entity: &Compound,
+    pos: (f64, f64, f64),
+    dimension: &str,
+    errors: &mut Vec<String>,
+) -> Marker {
+    // Renamed from `Motive` in 1.19
+    let variant = string(entity, "variant")
+        .or_else(|| string(entity, "Motive"))
+        .unwrap_or_default();
+    let name = short_id(&variant).to_string();
+    let picture = assets
+        .image(&format!("minecraft/textures/painting/{}.png", name))
+        .and_then(|image| {
+            let path = save_picture(assets, "painting", &name, image.clone(), errors)?;
+            let resolution = assets.resolution() as f32;
+            let size = (
+                image.width() as f32 / resolution,
+                image.height() as f32 / resolution,
+            );
+            // Horizontal facing, 0 south, 1 west, 2 north and 3 east
+            let facing = byte(entity, "facing").or_else(|| byte(entity, "Facing"));
+            let (dx, dz, rotation) = match facing {
+                Some(1) => (-1.0, 0.0, -PI / 2.0),
+                Some(2) => (0.0, -1.0, PI),
+                Some(3) => (1.0, 0.0, PI / 2.0),
+                _ => (0.0, 1.0, 0.0),
+            };
+            Some(Picture {
+                path,
+                centre: (
+                    pos.0 as f32 + dx * size.1 / 2.0,
+                    pos.2 as f32 + dz * size.1 / 2.0,
+                ),
+                size,
+                rotation,
+            })
+        });
+    Marker {
+        layer: Layer::Paintings,
+        label: name,
+        position: (
+            pos.0.floor() as i32,
+            pos.1.floor() as i32,
+            pos.2.floor() as i32,
+        ),
+        dimension: dimension.to_string(),
+        details: Vec::new(),
+        bounds: None,
+        icon: None,
+        picture,
+    }
+}
+
+fn item_frame(
+    assets: &Assets,
+    entity: &Compound,
+    position: (i32, i32, i32),
+    dimension: &str,
+    errors: &mut Vec<String>,
+) -> Marker {
+    let item = compound(entity, "Item")
+        .and_then(|item| string(item, "id"))
+        .map(|id| short_id(&id).to_string());
+    // Items without their own item texture are block items, they show their block texture instead
+    let picture = item.as_ref().and_then(|name| {
+        let image = assets
+            .image(&format!("minecraft/textures/item/{}.png", name))
+            .or_else(|| assets.block_texture(name))?;
+        Some(Picture {
+            path: save_picture(assets, "item", name, image, errors)?,
+            centre: (position.0 as f32 + 0.5, position.2 as f32 + 0.5),
+            size: (1.0, 1.0),
+            rotation: 0.0,
+        })
+    });
+    Marker {
+        layer: Layer::ItemFrames,
+        label: item.unwrap_or_else(|| "empty".to_string()),
+        position,
+        dimension: dimension.to_string(),
+        details: Vec::new(),
+        bounds: None,
+        icon: None,
+        picture,
+    }
+}
+
+// Pictures are cached per set of assets, the ones written for other versions are removed
+fn clear_stale_pictures(identity: &str) {
+    let entries = match fs::read_dir(PICTURE_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        if entry.file_name() != identity {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+// Writes a texture next to the tiles so the asset server can load it, returns its path relative to `saves`. A
+// picture that could not be written is added to `errors` and the marker is shown without it.
+fn save_picture(
+    assets: &Assets,
+    kind: &str,
+    name: &str,
+    image: DynamicImage,
+    errors: &mut Vec<String>,
+) -> Option<String> {
+    let dir = format!("{}\\{}\\{}", PICTURE_DIR, assets.identity(), kind);
+    let file = format!("{}\\{}.png", dir, name);
+    if !Path::new(&file).exists() {
+        // Animated textures are a vertical strip of frames, the first one is enough
+        let size = image.width().min(image.height());
+        let frame = match kind {
+            "item" | "entity" => imageops::crop_imm(&image, 0, 0, size, size).to_image(),
+            _ => image.into_rgba8(),
+        };
+        if let Err(e) = fs::create_dir_all(&dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| frame.save(&file).map_err(|e| e.to_string()))
+        {
+            errors.push(format!("Could not save {}: {}", file, e));
+            return None;
+        }
+    }
+    Some(file["saves\\".len()..].to_string())
+}
@@ -0,0 +1,88 @@
+use std::{collections::HashMap, ffi::OsStr, fs, path::Path};
+
+use serde_json::Value;
+
+use super::{Layer, LoadResult, Marker, OVERWORLD};
+use crate::tags::{int, position, read_gzip, string};
+
+// Player names by uuid, servers keep the cache next to the world folder rather than in it
+fn user_cache(save_path: &Path) -> HashMap<String, String> {
+    let cache = [Some(save_path), save_path.parent()]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("usercache.json"))
+        .find_map(|path| fs::read_to_string(path).ok())
+        .and_then(|s| serde_json::from_str::<Value>(&s).ok());
+    cache
+        .as_ref()
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            Some((
+                entry.get("uuid")?.as_str()?.to_string(),
+                entry.get("name")?.as_str()?.to_string(),
+            ))
+        })
+        .collect()
+}
+
+// Where each player logged off and where they will respawn
+pub fn load(save_path: &Path) -> LoadResult {
+    let dir = save_path.join("playerdata");
+    // Worlds that were only ever played in singleplayer before 1.7 have no player data folder
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => return (Vec::new(), Vec::new()),
+    };
+    let names = user_cache(save_path);
+    let mut markers = Vec::new();
+    let mut errors = Vec::new();
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if path.extension() != Some(OsStr::new("dat")) {
+            continue;
+        }
+        let uuid = path.file_stem().unwrap().to_string_lossy().to_string();
+        let player = match read_gzip(&path) {
+            Ok(player) => player,
+            Err(e) => {
+                errors.push(format!(
+                    "Could not read player data {}: {}",
+                    path.display(),
+                    e
+                ));
+                continue;
+            }
+        };
+        let name = names.get(&uuid).cloned().unwrap_or_else(|| uuid.clone());
+        if let Some((x, y, z)) = position(&player, "Pos") {
+            markers.push(Marker {
+                layer: Layer::Players,
+                label: name.clone(),
+                position: (x.floor() as i32, y.floor() as i32, z.floor() as i32),
+                dimension: string(&player, "Dimension").unwrap_or_else(|| OVERWORLD.to_string()),
+                details: vec![uuid.clone()],
+                bounds: None,
+                icon: None,
+                picture: None,
+            });
+        }
+        if let Some(((x, y), z)) = int(&player, "SpawnX")
+            .zip(int(&player, "SpawnY"))
+            .zip(int(&player, "SpawnZ"))
+        {
+            markers.push(Marker {
+                layer: Layer::Respawns,
+                label: name,
+                position: (x, y, z),
+                dimension: string(&player, "SpawnDimension")
+                    .unwrap_or_else(|| OVERWORLD.to_string()),
+                details: vec![uuid],
+                bounds: None,
+                icon: None,
+                picture: None,
+            });
+        }
+    }
+    (markers, errors)
+}
@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use nbt::Value;
+
+use super::{read_chunks, region_files, short_id, Layer, LoadResult, Marker, DIMENSIONS};
+use crate::tags::{compound, int_array, list, string};
+
+// Every point of interest the game tracks, beds, bells, job sites, portals and lodestones
+pub fn load(save_path: &Path) -> LoadResult {
+    let mut markers = Vec::new();
+    let mut errors = Vec::new();
+    for (folder, dimension) in DIMENSIONS {
+        for path in region_files(&save_path.join(folder).join("poi")) {
+            for chunk in read_chunks(&path, &mut errors) {
+                let sections = match compound(&chunk, "Sections") {
+                    Some(sections) => sections,
+                    None => continue,
+                };
+                let records = sections
+                    .values()
+                    .filter_map(|section| match section {
+                        Value::Compound(section) => list(section, "Records"),
+                        _ => None,
+                    })
+                    .flatten();
+                for record in records {
+                    let record = match record {
+                        Value::Compound(record) => record,
+                        _ => continue,
+                    };
+                    let (kind, pos) = match string(record, "type").zip(int_array(record, "pos")) {
+                        Some((kind, pos)) if pos.len() == 3 => (kind, pos),
+                        _ => continue,
+                    };
+                    // Lodestones get their own layer, they are placed on purpose to mark somewhere
+                    let layer = if kind == "minecraft:lodestone" {
+                        Layer::Lodestones
+                    } else {
+                        Layer::PointsOfInterest
+                    };
+                    markers.push(Marker {
+                        layer,
+                        label: short_id(&kind).to_string(),
+                        position: (pos[0], pos[1], pos[2]),
+                        dimension: dimension.to_string(),
+                        details: Vec::new(),
+                        bounds: None,
+                        icon: None,
+                        picture: None,
+                    });
+                }
+            }
+        }
+    }
+    (markers, errors)
+}
@@ -0,0 +1,64 @@
+use nbt::{Blob, Value};
+
+use super::{short_id, Layer, Marker};
+use crate::tags::{compound, int_array, list, string};
+
+// Structure starts saved in a chunk, each with the bounding box of all of its pieces
+pub fn from_chunk(chunk: &Blob, dimension: &str, markers: &mut Vec<Marker>) {
+    let starts = match compound(chunk, "structures").and_then(|s| compound(s, "starts")) {
+        Some(starts) => starts,
+        None => return,
+    };
+    for start in starts.values() {
+        let start = match start {
+            Value::Compound(start) => start,
+            _ => continue,
+        };
+        let id = match string(start, "id") {
+            Some(id) if id != "INVALID" => id,
+            _ => continue,
+        };
+        let bounds = list(start, "Children")
+            .into_iter()
+            .flatten()
+            .filter_map(|child| match child {
+                Value::Compound(child) => int_array(child, "BB"),
+                _ => None,
+            })
+            .filter(|bb| bb.len() == 6)
+            .fold(None, |bounds: Option<[i32; 6]>, bb| {
+                Some(match bounds {
+                    Some(b) => [
+                        b[0].min(bb[0]),
+                        b[1].min(bb[1]),
+                        b[2].min(bb[2]),
+                        b[3].max(bb[3]),
+                        b[4].max(bb[4]),
+                        b[5].max(bb[5]),
+                    ],
+                    None => [bb[0], bb[1], bb[2], bb[3], bb[4], bb[5]],
+                })
+            });
+        let bb = match bounds {
+            Some(bb) => bb,
+            None => continue,
+        };
+        markers.push(Marker {
+            layer: Layer::Structures,
+            label: short_id(&id).to_string(),
+            position: (
+                (bb[0] + bb[3]) / 2,
+                (bb[1] + bb[4]) / 2,
+                (bb[2] + bb[5]) / 2,
+            ),
+            dimension: dimension.to_string(),
+            details: vec![format!(
+                "{} {} {} to {} {} {}",
+                bb[0], bb[1], bb[2], bb[3], bb[4], bb[5]
+            )],
+            bounds: Some([bb[0], bb[2], bb[3], bb[5]]),
+            icon: None,
+            picture: None,
+        });
+    }
+}
@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use image::Rgb;
+
+use crate::{
+    jobs::{JobManager, Priority},
+    level::ChunkFormat,
+    markers::MarkerSprite,
+    render::{
+        analysis::{block_light_color, height_color, sky_light_color, MAX_Y, UNKNOWN},
+        biomes::BIOME_COLORS,
+        mode::{HeightSource, RenderMode},
+    },
+    tiles::TileManager,
+    UIState,
+};
+
+#[derive(Default)]
+pub struct ModeSettings {
+    // Height biomes are sampled at when not following the surface
+    biome_y: i32,
+    fixed_height: bool,
+}
+
+fn swatch(ui: &mut egui::Ui, Rgb([r, g, b]): Rgb<u8>, label: &str) {
+    ui.horizontal(|ui| {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(12.0, 12.0), egui::Sense::hover());
+        ui.painter()
+            .rect_filled(rect, 0.0, egui::Color32::from_rgb(r, g, b));
+        ui.label(label);
+    });
+}
+
+fn legend(ui: &mut egui::Ui, mode: RenderMode, min_y: i32) {
+    egui::CollapsingHeader::new("Legend").show(ui, |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| match mode {
+                RenderMode::Terrain => {}
+                RenderMode::Biomes(_) => {
+                    for (name, color) in BIOME_COLORS {
+                        swatch(ui, Rgb(color), name);
+                    }
+                }
+                RenderMode::Height(_) => {
+                    for y in (min_y..=MAX_Y).step_by(32) {
+                        swatch(ui, height_color(y, min_y), &format!("Y {}", y));
+                    }
+                }
+                RenderMode::BlockLight => {
+                    swatch(ui, block_light_color(0), "0, monsters can spawn");
+                    for level in 1..=15 {
+                        swatch(ui, block_light_color(level), &level.to_string());
+                    }
+                    swatch(ui, UNKNOWN, "Light not calculated");
+                }
+                RenderMode::SkyLight => {
+                    for level in 0..=15 {
+                        swatch(ui, sky_light_color(level), &level.to_string());
+                    }
+                    swatch(ui, UNKNOWN, "Light not calculated");
+                }
+                RenderMode::Night => {
+                    ui.label("Terrain at midnight, lit by torches, lava and other light sources");
+                }
+            });
+    });
+}
+
+// Picks what the tiles show, the map is cleared and streamed again in the new mode
+pub fn render_mode_window(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    mut settings: ResMut<ModeSettings>,
+    mut ui_state: ResMut<UIState>,
+    mut jobs: ResMut<JobManager>,
+    mut tiles: ResMut<TileManager>,
+    sprites: Query<Entity, (With<Sprite>, Without<MarkerSprite>)>,
+) {
+    if ui_state.save_path.is_empty() {
+        return;
+    }
+    let min_y = match &ui_state.level {
+        Some(Ok(level)) => level.chunk_format(),
+        _ => ChunkFormat::CavesAndCliffs,
+    }
+    .min_y();
+    let mut mode = ui_state.render_mode;
+    egui::Window::new("Render Mode").show(egui_context.ctx_mut(), |ui| {
+        let biomes = RenderMode::Biomes(settings.fixed_height.then(|| settings.biome_y));
+        for choice in [
+            RenderMode::Terrain,
+            biomes,
+            RenderMode::Height(HeightSource::WorldSurface),
+            RenderMode::BlockLight,
+            RenderMode::SkyLight,
+            RenderMode::Night,
+        ] {
+            let selected = std::mem::discriminant(&mode) == std::mem::discriminant(&choice);
+            if ui.radio(selected, choice.label()).clicked() && !selected {
+                mode = choice;
+            }
+        }
+        match mode {
+            RenderMode::Biomes(_) => {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut settings.fixed_height, "At Y");
+                    let response = ui.add_enabled(
+                        settings.fixed_height,
+                        egui::DragValue::new(&mut settings.biome_y).clamp_range(min_y..=MAX_Y - 1),
+                    );
+                    // Every height is a separate set of tiles, wait until dragging stops
+                    if !response.dragged() {
+                        mode = RenderMode::Biomes(settings.fixed_height.then(|| settings.biome_y));
+                    }
+                });
+            }
+            RenderMode::Height(source) => {
+                let mut source = source;
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut source, HeightSource::WorldSurface, "Surface");
+                    ui.radio_value(&mut source, HeightSource::OceanFloor, "Ocean floor");
+                });
+                mode = RenderMode::Height(source);
+            }
+            _ => {}
+        }
+        if mode != RenderMode::Terrain {
+            legend(ui, mode, min_y);
+        }
+    });
+    if mode == ui_state.render_mode {
+        return;
+    }
+    ui_state.render_mode = mode;
+    // Tiles of the old mode that are still on their way are no use anymore
+    jobs.cancel_priority(Priority::Viewport);
+    jobs.cancel_priority(Priority::Background);
+    for entity in sprites.iter() {
+        commands.entity(entity).despawn();
+    }
+    tiles.clear();
+    ui_state.viewport_moved = true;
+}
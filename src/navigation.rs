@@ -0,0 +1,173 @@
+use std::fs;
+
+use bevy::{prelude::*, render::camera::Camera2d};
+use bevy_egui::{egui, EguiContext};
+use serde_json::{json, Value};
+
+use crate::{inspector::BLOCK_SIZE, UIState};
+
+pub struct Bookmark {
+    pub name: String,
+    pub x: i32,
+    pub z: i32,
+}
+
+#[derive(Default)]
+pub struct Navigation {
+    goto_x: String,
+    goto_z: String,
+    bookmark_name: String,
+    bookmarks: Vec<Bookmark>,
+    spawn: Option<(i32, i32)>,
+    // Invalid coordinates or a failed bookmark save, shown until the next attempt
+    error: Option<String>,
+    // The world the bookmarks and spawn were loaded for
+    save_name: String,
+}
+
+// Bookmarks are kept next to the world's tiles so each world has its own
+fn bookmarks_path(save_name: &str) -> String {
+    format!("saves\\{}\\bookmarks.json", save_name)
+}
+
+fn load_bookmarks(save_name: &str) -> Vec<Bookmark> {
+    let value = match fs::read_to_string(bookmarks_path(save_name))
+        .ok()
+        .and_then(|s| serde_json::from_str::<Value>(&s).ok())
+    {
+        Some(value) => value,
+        None => return Vec::new(),
+    };
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|b| {
+            Some(Bookmark {
+                name: b.get("name")?.as_str()?.to_string(),
+                x: b.get("x")?.as_i64()? as i32,
+                z: b.get("z")?.as_i64()? as i32,
+            })
+        })
+        .collect()
+}
+
+fn save_bookmarks(save_name: &str, bookmarks: &[Bookmark]) -> Result<(), String> {
+    let value = bookmarks
+        .iter()
+        .map(|b| json!({ "name": b.name, "x": b.x, "z": b.z }))
+        .collect::<Vec<Value>>();
+    let path = bookmarks_path(save_name);
+    fs::create_dir_all(format!("saves\\{}", save_name))
+        .and_then(|_| fs::write(&path, serde_json::to_string_pretty(&value).unwrap()))
+        .map_err(|e| format!("Could not save {}: {}", path, e))
+}
+
+// Puts the block at the centre of the screen
+pub fn centre_on(transform: &mut Transform, (x, z): (i32, i32)) {
+    transform.translation.x = (x as f32 + 0.5) * BLOCK_SIZE;
+    transform.translation.y = -(z as f32 + 0.5) * BLOCK_SIZE;
+}
+
+pub fn navigation_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut navigation: ResMut<Navigation>,
+    mut ui_state: ResMut<UIState>,
+    mut cameras: Query<&mut Transform, With<Camera2d>>,
+) {
+    if ui_state.save_path.is_empty() {
+        return;
+    }
+    let mut camera = match cameras.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let mut target = None;
+
+    // A new world was opened, start out at its spawn
+    if navigation.save_name != ui_state.save_name {
+        navigation.save_name = ui_state.save_name.clone();
+        navigation.bookmarks = load_bookmarks(&ui_state.save_name);
+        navigation.spawn = match &ui_state.level {
+            Some(Ok(level)) => level.spawn.map(|(x, _, z)| (x, z)),
+            _ => None,
+        };
+        target = navigation.spawn;
+    }
+
+    let centre = (
+        (camera.translation.x / BLOCK_SIZE).floor() as i32,
+        (-camera.translation.y / BLOCK_SIZE).floor() as i32,
+    );
+    let mut remove = None;
+    let mut add = false;
+    egui::Window::new("Navigation").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("X");
+            ui.add(egui::TextEdit::singleline(&mut navigation.goto_x).desired_width(60.0));
+            ui.label("Z");
+            ui.add(egui::TextEdit::singleline(&mut navigation.goto_z).desired_width(60.0));
+            if ui.button("Go").clicked() {
+                match (
+                    navigation.goto_x.trim().parse(),
+                    navigation.goto_z.trim().parse(),
+                ) {
+                    (Ok(x), Ok(z)) => {
+                        target = Some((x, z));
+                        navigation.error = None;
+                    }
+                    _ => navigation.error = Some("X and Z must be whole numbers".to_string()),
+                }
+            }
+        });
+        if let Some(error) = &navigation.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        if let Some(spawn) = navigation.spawn {
+            if ui
+                .button(format!("Spawn ({} {})", spawn.0, spawn.1))
+                .clicked()
+            {
+                target = Some(spawn);
+            }
+        }
+        ui.separator();
+        for (i, bookmark) in navigation.bookmarks.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.small_button("x").clicked() {
+                    remove = Some(i);
+                }
+                if ui
+                    .button(format!("{} ({} {})", bookmark.name, bookmark.x, bookmark.z))
+                    .clicked()
+                {
+                    target = Some((bookmark.x, bookmark.z));
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut navigation.bookmark_name);
+            add = ui.button("Bookmark").clicked() && !navigation.bookmark_name.trim().is_empty();
+        });
+    });
+
+    if add {
+        let name = navigation.bookmark_name.trim().to_string();
+        navigation.bookmarks.push(Bookmark {
+            name,
+            x: centre.0,
+            z: centre.1,
+        });
+        navigation.bookmark_name.clear();
+    }
+    if let Some(i) = remove {
+        navigation.bookmarks.remove(i);
+    }
+    if add || remove.is_some() {
+        navigation.error = save_bookmarks(&ui_state.save_name, &navigation.bookmarks).err();
+    }
+    if let Some(target) = target {
+        centre_on(&mut camera, target);
+        ui_state.viewport_moved = true;
+    }
+}
@@ -0,0 +1,274 @@
+use std::{fs, time::SystemTime};
+
+use bevy::{prelude::*, render::camera::Camera2d};
+use bevy_egui::{
+    egui::{self, Color32, Pos2, Stroke},
+    EguiContext,
+};
+use serde_json::Value;
+
+use crate::{inspector::BLOCK_SIZE, markers::OVERWORLD, UIState};
+
+// How often the overlay file is checked for changes
+const RELOAD_INTERVAL: f32 = 1.0;
+const LINE_WIDTH: f32 = 2.0;
+const POINT_RADIUS: f32 = 4.0;
+const DEFAULT_COLOR: Color32 = Color32::from_rgb(255, 255, 0);
+
+pub enum Geometry {
+    Point((f32, f32)),
+    Line(Vec<(f32, f32)>),
+    Polygon(Vec<(f32, f32)>),
+}
+
+// A shape drawn over the map, coordinates are in blocks
+pub struct Overlay {
+    pub geometry: Geometry,
+    pub color: Color32,
+    pub label: Option<String>,
+    pub dimension: String,
+}
+
+#[derive(Default)]
+pub struct Overlays {
+    overlays: Vec<Overlay>,
+    error: Option<String>,
+    hidden: bool,
+    // The world the overlays were loaded for and when its file was last changed
+    save_name: String,
+    modified: Option<SystemTime>,
+    since_check: f32,
+}
+
+// Overlays are kept next to the world's tiles, like bookmarks
+pub fn overlays_path(save_name: &str) -> String {
+    format!("saves\\{}\\overlays.json", save_name)
+}
+
+// Block coordinates to a point on screen, the inverse of `cursor_world_position`
+pub fn to_screen(
+    window: &Window,
+    transform: &Transform,
+    projection: &OrthographicProjection,
+    (x, z): (f32, f32),
+) -> Pos2 {
+    Pos2::new(
+        (x * BLOCK_SIZE - transform.translation.x) / projection.scale + window.width() / 2.0,
+        (z * BLOCK_SIZE + transform.translation.y) / projection.scale + window.height() / 2.0,
+    )
+}
+
+// `#rrggbb` or `#rrggbbaa`
+fn parse_color(hex: &str) -> Option<Color32> {
+    let hex = hex.strip_prefix('#')?;
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    match hex.len() {
+        6 => Some(Color32::from_rgb(channel(0)?, channel(2)?, channel(4)?)),
+        8 => Some(Color32::from_rgba_unmultiplied(
+            channel(0)?,
+            channel(2)?,
+            channel(4)?,
+            channel(6)?,
+        )),
+        _ => None,
+    }
+}
+
+fn parse_points(value: &Value) -> Option<Vec<(f32, f32)>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|point| match point.as_array()?.as_slice() {
+            [x, z] => Some((x.as_f64()? as f32, z.as_f64()? as f32)),
+            _ => None,
+        })
+        .collect()
+}
+
+// The file is a list of features, each with a type of point, line or polygon, its block coordinates and an optional
+// colour, label and dimension. Points mark a block, lines and polygons run along block corners
+fn parse_overlays(json: &str) -> Result<Vec<Overlay>, String> {
+    let value = serde_json::from_str::<Value>(json).map_err(|e| e.to_string())?;
+    let features = value
+        .get("features")
+        .and_then(|f| f.as_array())
+        .ok_or("Missing a features list")?;
+    features
+        .iter()
+        .enumerate()
+        .map(|(i, feature)| {
+            let invalid = || format!("Feature {} is invalid", i);
+            let kind = feature
+                .get("type")
+                .and_then(|t| t.as_str())
+                .ok_or_else(invalid)?;
+            let points = feature
+                .get("points")
+                .and_then(parse_points)
+                .ok_or_else(invalid)?;
+            let geometry = match (kind, points.as_slice()) {
+                ("point", [point]) => Geometry::Point(*point),
+                ("line", [_, _, ..]) => Geometry::Line(points),
+                ("polygon", [_, _, _, ..]) => Geometry::Polygon(points),
+                _ => return Err(invalid()),
+            };
+            Ok(Overlay {
+                geometry,
+                color: match feature.get("color").and_then(|c| c.as_str()) {
+                    Some(hex) => parse_color(hex).ok_or_else(invalid)?,
+                    None => DEFAULT_COLOR,
+                },
+                label: feature
+                    .get("label")
+                    .and_then(|l| l.as_str())
+                    .map(|l| l.to_string()),
+                dimension: feature
+                    .get("dimension")
+                    .and_then(|d| d.as_str())
+                    .unwrap_or(OVERWORLD)
+                    .to_string(),
+            })
+        })
+        .collect()
+}
+
+// Rereads the overlay file whenever a different world is opened or the file is changed on disk
+pub fn reload_overlays(time: Res<Time>, ui_state: Res<UIState>, mut overlays: ResMut<Overlays>) {
+    if ui_state.save_name.is_empty() {
+        return;
+    }
+    overlays.since_check += time.delta_seconds();
+    let opened = overlays.save_name != ui_state.save_name;
+    if !opened && overlays.since_check < RELOAD_INTERVAL {
+        return;
+    }
+    overlays.since_check = 0.0;
+    if opened {
+        overlays.save_name = ui_state.save_name.clone();
+        overlays.modified = None;
+        overlays.overlays.clear();
+        overlays.error = None;
+    }
+    let path = overlays_path(&ui_state.save_name);
+    let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+    if modified == overlays.modified {
+        return;
+    }
+    overlays.modified = modified;
+    if modified.is_none() {
+        // The file was removed
+        overlays.overlays.clear();
+        overlays.error = None;
+        return;
+    }
+    match fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| parse_overlays(&json))
+    {
+        Ok(loaded) => {
+            overlays.overlays = loaded;
+            overlays.error = None;
+        }
+        // A half written file keeps the last good overlays on screen
+        Err(e) => overlays.error = Some(format!("Could not load {}: {}", path, e)),
+    }
+}
+
+pub fn draw_overlays(
+    mut egui_context: ResMut<EguiContext>,
+    mut overlays: ResMut<Overlays>,
+    ui_state: Res<UIState>,
+    windows: Res<Windows>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    if ui_state.save_path.is_empty() {
+        return;
+    }
+    let ctx = egui_context.ctx_mut();
+    let mut shown = !overlays.hidden;
+    egui::Window::new("Overlays").show(ctx, |ui| {
+        ui.label(overlays_path(&ui_state.save_name));
+        ui.checkbox(&mut shown, format!("Show ({})", overlays.overlays.len()));
+        if let Some(error) = &overlays.error {
+            ui.colored_label(Color32::RED, error);
+        }
+    });
+    overlays.hidden = !shown;
+    if !shown {
+        return;
+    }
+    let (transform, projection) = match cameras.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let window = windows.get_primary().unwrap();
+    let screen = |point| to_screen(window, transform, projection, point);
+    // Drawn behind every window but over the map
+    let painter = ctx.layer_painter(egui::LayerId::background());
+    for overlay in overlays
+        .overlays
+        .iter()
+        .filter(|o| o.dimension == OVERWORLD)
+    {
+        let stroke = Stroke::new(LINE_WIDTH, overlay.color);
+        let anchor = match &overlay.geometry {
+            Geometry::Point((x, z)) => {
+                let centre = screen((x + 0.5, z + 0.5));
+                painter.circle_filled(centre, POINT_RADIUS, overlay.color);
+                centre
+            }
+            Geometry::Line(points) => {
+                let points = points.iter().map(|p| screen(*p)).collect::<Vec<Pos2>>();
+                painter.add(egui::Shape::line(points.clone(), stroke));
+                points[0]
+            }
+            Geometry::Polygon(points) => {
+                let points = points.iter().map(|p| screen(*p)).collect::<Vec<Pos2>>();
+                let sum = points
+                    .iter()
+                    .fold(egui::Vec2::ZERO, |sum, p| sum + p.to_vec2());
+                let centre = Pos2::ZERO + sum / points.len() as f32;
+                painter.add(egui::Shape::closed_line(points, stroke));
+                centre
+            }
+        };
+        if let Some(label) = &overlay.label {
+            painter.text(
+                anchor,
+                egui::Align2::CENTER_BOTTOM,
+                label,
+                egui::FontId::default(),
+                overlay.color,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_egui::egui::Color32;
+
+    use super::parse_color;
+
+    #[test]
+    fn parses_rgb_and_rgba() {
+        assert_eq!(parse_color("#ff8000"), Some(Color32::from_rgb(255, 128, 0)));
+        assert_eq!(
+            parse_color("#00ff0080"),
+            Some(Color32::from_rgba_unmultiplied(0, 255, 0, 128))
+        );
+        assert_eq!(
+            parse_color("#ABCDEF"),
+            Some(Color32::from_rgb(171, 205, 239))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_colors() {
+        assert_eq!(parse_color("ff8000"), None);
+        assert_eq!(parse_color("#ff80"), None);
+        assert_eq!(parse_color("#gg8000"), None);
+        assert_eq!(parse_color("#ff8000f"), None);
+        assert_eq!(parse_color("#ffé00"), None);
+    }
+}
@@ -1,13 +1,30 @@
 use std::{
     fs,
+    panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
 };
 
 use bevy::utils::HashMap;
-use image::{open, DynamicImage, GenericImageView, ImageBuffer, Pixel, Rgba, RgbaImage};
+use image::{
+    imageops::FilterType, DynamicImage, GenericImageView, ImageBuffer, Pixel, Rgba, RgbaImage,
+};
 use simple_anvil::{block::Block, chunk::Chunk};
 
+use crate::{
+    anvil,
+    assets::Assets,
+    level::{ChunkFormat, Level},
+    world::WorldView,
+};
+
+use self::{diagnostics::Diagnostics, error::RenderError, mode::RenderMode};
+
+pub mod analysis;
+pub mod biomes;
+pub mod diagnostics;
+pub mod error;
+pub mod mode;
 mod models;
 
 pub const NON_SOLID: [&str; 11] = [
@@ -24,80 +41,395 @@ pub const NON_SOLID: [&str; 11] = [
     "cave_air",
 ];
 
+// Chunk statuses from the features step on, by then the surface blocks are placed
+const SURFACE_STATUSES: [&str; 5] = [
+    "features",
+    "initialize_light",
+    "light",
+    "spawn",
+    "heightmaps",
+];
+// Darkens every other stripe of partially generated chunks so they stand out from finished ones
+const PROTO_STRIPE: u32 = 4;
+const PROTO_SHADE: f32 = 0.6;
+// Raised when tiles cached by an older version cannot be trusted, every tile set is rendered again once. Version 2
+// moved partially generated chunks into their own tile sets.
+const TILE_FORMAT: u32 = 2;
+
+pub fn is_full(status: &str) -> bool {
+    status.strip_prefix("minecraft:").unwrap_or(status) == "full"
+}
+
+// Whether a chunk has anything worth drawing yet
+pub fn is_renderable(status: &str, proto_chunks: bool) -> bool {
+    let status = status.strip_prefix("minecraft:").unwrap_or(status);
+    is_full(status) || (proto_chunks && SURFACE_STATUSES.contains(&status))
+}
+
+// Shown in place of chunks that failed to render, relative to the asset folder
+pub const ERROR_TILE: &str = "error_tile.png";
+
+pub fn write_error_tile() -> Result<(), RenderError> {
+    let mut tile = RgbaImage::new(256, 256);
+    for x in 0..256 {
+        for z in 0..256 {
+            // Red diagonal stripes
+            let pixel = if (x + z) / 16 % 2 == 0 {
+                Rgba([200, 30, 30, 255])
+            } else {
+                Rgba([60, 0, 0, 255])
+            };
+            tile.put_pixel(x, z, pixel);
+        }
+    }
+    fs::create_dir_all("saves")?;
+    tile.save(format!("saves\\{}", ERROR_TILE))?;
+    Ok(())
+}
+
+// Path of an existing tile for the chunk if it is at least as new as the chunk itself
+pub fn cached_tile(
+    save_name: &str,
+    chunk_coords: (i32, i32),
+    last_update: i64,
+) -> Result<Option<String>, RenderError> {
+    let dir = std::env::current_dir()?
+        .join("saves")
+        .join(save_name)
+        .join(format!(
+            "r.{}.{}",
+            chunk_coords.0.div_euclid(32),
+            chunk_coords.1.div_euclid(32)
+        ));
+    if !dir.exists() {
+        return Ok(None);
+    }
+    let prefix = format!(
+        "chunk{}.{}.",
+        chunk_coords.0.rem_euclid(32),
+        chunk_coords.1.rem_euclid(32)
+    );
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some(rest) = name.strip_prefix(&prefix) {
+            let rendered = rest
+                .trim_end_matches(".png")
+                .parse::<i64>()
+                .map_err(|_| RenderError::InvalidTileName(name.clone()))?;
+            if rendered >= last_update {
+                return Ok(Some(entry.path().to_string_lossy().to_string()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Region images are written at each of these sizes in pixels, zoomed out views show the smallest one that is still
+// sharp so a screen full of regions never holds more than a few megabytes
+pub const REGION_IMAGE_SIZES: [u32; 3] = [1024, 256, 64];
+
+fn region_image_path(save_name: &str, region_name: &str, size: u32) -> String {
+    format!(
+        "saves\\{}\\{}\\{}.{}.png",
+        save_name, region_name, region_name, size
+    )
+}
+
+// Path of the region image of a size, the chunk tiles of the region are stitched again first when any of them is
+// newer than the image
+pub fn stitch_region(
+    save_name: &str,
+    region_folder: &Path,
+    size: u32,
+) -> Result<String, RenderError> {
+    let region_name = region_folder
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| RenderError::InvalidTileName(region_folder.display().to_string()))?;
+    let img_path = region_image_path(save_name, &region_name, size);
+
+    // Chunks rendered again leave their older tiles behind, only the newest tile of each chunk is used
+    let mut tiles: HashMap<(u32, u32), (i64, PathBuf)> = HashMap::new();
+    let mut newest = None;
+    for entry in fs::read_dir(region_folder)?.filter_map(|f| f.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let parts = match name
+            .strip_prefix("chunk")
+            .and_then(|n| n.strip_suffix(".png"))
+        {
+            Some(rest) => rest.split('.').collect::<Vec<&str>>(),
+            None => continue,
+        };
+        let (x, z, rendered) = match parts.as_slice() {
+            [x, z, rendered] => match (x.parse(), z.parse(), rendered.parse::<i64>()) {
+                (Ok(x), Ok(z), Ok(rendered)) => (x, z, rendered),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        newest = newest.max(entry.metadata().and_then(|m| m.modified()).ok());
+        let newer = match tiles.get(&(x, z)) {
+            Some((r, _)) => rendered > *r,
+            None => true,
+        };
+        if newer {
+            tiles.insert((x, z), (rendered, entry.path()));
+        }
+    }
+    let stitched = fs::metadata(&img_path).and_then(|m| m.modified()).ok();
+    if stitched.is_some() && stitched >= newest {
+        return Ok(img_path);
+    }
+
+    let cell = REGION_IMAGE_SIZES[0] / 32;
+    let mut img = RgbaImage::new(REGION_IMAGE_SIZES[0], REGION_IMAGE_SIZES[0]);
+    for x in 0..32 {
+        for z in 0..32 {
+            match tiles.get(&(x, z)) {
+                Some((_, path)) => {
+                    let chunk_img = image::open(path)?.into_rgba8();
+                    let chunk_img =
+                        image::imageops::resize(&chunk_img, cell, cell, FilterType::Triangle);
+                    image::imageops::replace(
+                        &mut img,
+                        &chunk_img,
+                        (x * cell) as i64,
+                        (z * cell) as i64,
+                    );
+                }
+                None => {
+                    // Chunks without a tile get a grey checkerboard
+                    let square = (cell / 4).max(1);
+                    for cx in 0..cell {
+                        for cz in 0..cell {
+                            let pixel = if (cx / square + cz / square) % 2 == 1 {
+                                Rgba::from([100, 100, 100, 255])
+                            } else {
+                                Rgba::from([150, 150, 150, 255])
+                            };
+                            img.put_pixel(x * cell + cx, z * cell + cz, pixel);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for level in REGION_IMAGE_SIZES {
+        let path = region_image_path(save_name, &region_name, level);
+        if level == img.width() {
+            img.save(&path)?;
+        } else {
+            image::imageops::resize(&img, level, level, FilterType::Triangle).save(&path)?;
+        }
+    }
+    // Full resolution region images from before the sizes were introduced are never shown again
+    let _ = fs::remove_file(format!(
+        "saves\\{}\\{}\\{}.png",
+        save_name, region_name, region_name
+    ));
+    Ok(img_path)
+}
+
+// Tiles are only valid for the asset stack they were rendered with, throw them away when the stack changes
+pub fn check_tile_cache(save_name: &str, assets: &Assets) -> Result<(), RenderError> {
+    let dir = std::env::current_dir()?.join("saves").join(save_name);
+    let id_file = dir.join("assets.id");
+    let id = format!("{}.{}", TILE_FORMAT, assets.identity());
+    if fs::read_to_string(&id_file).ok().as_deref() != Some(id.as_str()) {
+        if dir.exists() {
+            for entry in fs::read_dir(&dir)?.filter_map(|f| f.ok()) {
+                if entry.path().is_dir() {
+                    fs::remove_dir_all(entry.path())?;
+                }
+            }
+        }
+        fs::create_dir_all(&dir)?;
+        fs::write(id_file, id)?;
+    }
+    Ok(())
+}
+
+// What a block id resolved to, blocks without any texture are cached too so their lookups are not repeated
+#[derive(Clone)]
+pub struct CachedTexture {
+    image: Option<DynamicImage>,
+    // Found by one of the guessing heuristics, every hit is counted in the diagnostics
+    fallback: bool,
+}
+
+// Everything shared by the chunks of a single render job
+pub struct RenderContext {
+    pub world: WorldView,
+    pub assets: Arc<Assets>,
+    pub save_name: String,
+    pub format: ChunkFormat,
+    pub proto_chunks: bool,
+    pub mode: RenderMode,
+    // Shared with later jobs using the same assets
+    pub texture_cache: Arc<Mutex<HashMap<String, CachedTexture>>>,
+    pub diagnostics: Mutex<Diagnostics>,
+}
+
+impl RenderContext {
+    pub fn new(
+        save_path: &str,
+        save_name: &str,
+        resource_packs: &[String],
+        proto_chunks: bool,
+        mode: RenderMode,
+    ) -> Result<Self, RenderError> {
+        // The chunk format comes from the world's data version, a missing level.dat is treated as current
+        let format = Level::read(save_path)
+            .map(|level| level.chunk_format())
+            .unwrap_or(ChunkFormat::CavesAndCliffs);
+        if !format.is_supported() {
+            return Err(RenderError::UnsupportedChunkFormat(format.label()));
+        }
+        let assets = Assets::new(resource_packs);
+        check_tile_cache(save_name, &assets)?;
+        Ok(RenderContext {
+            world: WorldView::new(save_path),
+            assets: Arc::new(assets),
+            save_name: save_name.to_string(),
+            format,
+            proto_chunks,
+            mode,
+            texture_cache: Arc::new(Mutex::new(HashMap::new())),
+            diagnostics: Mutex::new(Diagnostics::default()),
+        })
+    }
+
+    // A context for another job with the same settings. Textures stay cached, chunks are read from disk again so
+    // changes since the last job show up and the diagnostics only cover the new job
+    pub fn for_job(&self) -> Self {
+        RenderContext {
+            world: self.world.reopen(),
+            assets: self.assets.clone(),
+            save_name: self.save_name.clone(),
+            format: self.format,
+            proto_chunks: self.proto_chunks,
+            mode: self.mode,
+            texture_cache: self.texture_cache.clone(),
+            diagnostics: Mutex::new(Diagnostics::default()),
+        }
+    }
+}
+
+// Renders a chunk to a tile and returns the path of the tile. Panics from simple_anvil on malformed chunk data are
+// turned into errors so a bad chunk never takes down the worker rendering it
 pub fn render_chunk(
-    chunk: Chunk,
-    region_file_name: String,
-    region_path: PathBuf,
-    save_name: String,
-    texture_cache: Arc<Mutex<HashMap<String, DynamicImage>>>,
-) -> String {
-    let surface_map = chunk.get_heightmap(false).unwrap();
-    let ocean_floor = chunk.get_heightmap(true).unwrap();
-
-    let mut chunk_image = RgbaImage::new(256, 256);
+    chunk: &Chunk,
+    region_coords: (i32, i32),
+    ctx: &RenderContext,
+) -> Result<String, RenderError> {
+    panic::catch_unwind(AssertUnwindSafe(|| draw_chunk(chunk, region_coords, ctx))).unwrap_or_else(
+        |e| {
+            Err(RenderError::CorruptChunk(
+                (
+                    region_coords.0 * 32 + chunk.x as i32,
+                    region_coords.1 * 32 + chunk.z as i32,
+                ),
+                RenderError::panic_message(e),
+            ))
+        },
+    )
+}
+
+fn draw_chunk(
+    chunk: &Chunk,
+    region_coords: (i32, i32),
+    ctx: &RenderContext,
+) -> Result<String, RenderError> {
+    let save_name = &ctx.save_name;
+    let region_file_name = format!("r.{}.{}.mca", region_coords.0, region_coords.1);
+    // World coordinates of the north west corner of the chunk
+    let origin_x = (region_coords.0 * 32 + chunk.x as i32) * 16;
+    let origin_z = (region_coords.1 * 32 + chunk.z as i32) * 16;
+    let chunk_coords = (origin_x / 16, origin_z / 16);
+    let surface_map = chunk
+        .get_heightmap(false)
+        .ok_or(RenderError::MissingHeightmap(chunk_coords))?;
+    let ocean_floor = chunk
+        .get_heightmap(true)
+        .ok_or(RenderError::MissingHeightmap(chunk_coords))?;
+
+    // Light modes read the chunk a second time for the light data simple_anvil skips
+    let light = if ctx.mode.needs_light() {
+        anvil::read_chunk(
+            ctx.world.region_path(region_coords.0, region_coords.1),
+            (chunk.x, chunk.z),
+        )
+        .map_err(|e| RenderError::CorruptChunk(chunk_coords, e.to_string()))?
+    } else {
+        None
+    };
+
+    let resolution = ctx.assets.resolution();
+    let mut chunk_image = RgbaImage::new(16 * resolution, 16 * resolution);
     for x in 0..16 {
         for z in 0..16 {
-            let texture_cache = texture_cache.clone();
             let y = surface_map[16 * z + x];
-            let block = chunk.get_block(x as i32, y, z as i32);
-            if block.id == "cave_air" {
-                println!(
-                    "Cave Air could be: {}",
-                    chunk.get_block(x as i32, ocean_floor[16 * z + x], z as i32)
-                );
-            }
-
-            let mut texture = get_texture(
-                &block,
-                &region_file_name,
-                &region_path,
-                texture_cache.clone(),
-            );
-            let dims = texture.dimensions();
-            if dims.0 > 16 || dims.1 > 16 {
-                texture = texture.crop(0, 0, 16, 16);
+            if let Some(color) = analysis::column_color(
+                ctx,
+                chunk,
+                light.as_ref(),
+                (x, z),
+                y,
+                ocean_floor[16 * z + x],
+            ) {
+                let pixel = Rgba([color[0], color[1], color[2], 255]);
+                for px in 0..resolution {
+                    for pz in 0..resolution {
+                        chunk_image.put_pixel(
+                            x as u32 * resolution + px,
+                            z as u32 * resolution + pz,
+                            pixel,
+                        );
+                    }
+                }
+                continue;
             }
+            let block = chunk.get_block(x as i32, y, z as i32);
 
+            let position = (origin_x + x as i32, y, origin_z + z as i32);
+            let texture = get_texture(&block, position, ctx)?;
             let mut block_img = texture.into_rgba8();
 
-            merge_colors(block, &chunk, &mut block_img);
-            merge_background(
-                &mut block_img,
-                &chunk,
-                x as i32,
-                y,
-                z as i32,
-                &region_file_name,
-                &region_path,
-                texture_cache,
-            );
+            merge_colors(block, position, ctx, &mut block_img);
+            merge_background(&mut block_img, position, ctx)?;
+            if ctx.mode == RenderMode::Night {
+                analysis::shade_night(&mut block_img, light.as_ref(), (x, z), y);
+            }
 
             image::imageops::overlay(
                 &mut chunk_image,
                 &block_img,
-                (x * 16) as i64,
-                (z * 16) as i64,
+                (x as u32 * resolution) as i64,
+                (z as u32 * resolution) as i64,
             );
         }
     }
 
+    if !is_full(chunk.get_status()) {
+        shade_proto_chunk(&mut chunk_image);
+    }
+
     let mut path = format!(
         "{}\\saves\\{}\\{}",
-        std::env::current_dir().unwrap().to_str().unwrap(),
+        std::env::current_dir()?.display(),
         save_name,
         &region_file_name[0..region_file_name.len() - 4]
     );
-    fs::create_dir_all(&path).unwrap();
-    chunk_image
-        .save(&format!(
-            "saves\\{}\\{}\\chunk{}.{}.{}.png",
-            save_name,
-            &region_file_name[0..region_file_name.len() - 4],
-            chunk.x,
-            chunk.z,
-            chunk.get_last_update()
-        ))
-        .unwrap();
+    fs::create_dir_all(&path)?;
+    chunk_image.save(&format!(
+        "saves\\{}\\{}\\chunk{}.{}.{}.png",
+        save_name,
+        &region_file_name[0..region_file_name.len() - 4],
+        chunk.x,
+        chunk.z,
+        chunk.get_last_update()
+    ))?;
 
     path.push_str(
         format!(
@@ -108,13 +440,70 @@ pub fn render_chunk(
         )
         .as_str(),
     );
-    path
+    Ok(path)
+}
+
+fn shade_proto_chunk(chunk_image: &mut RgbaImage) {
+    let stripe = PROTO_STRIPE * chunk_image.width() / 256;
+    for (x, z, pixel) in chunk_image.enumerate_pixels_mut() {
+        if ((x + z) / stripe.max(1)) & 1 == 0 {
+            for channel in pixel.0.iter_mut().take(3) {
+                *channel = (*channel as f32 * PROTO_SHADE) as u8;
+            }
+        }
+    }
+}
+
+fn merge_colors(
+    block: Block,
+    position: (i32, i32, i32),
+    ctx: &RenderContext,
+    block_img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+) {
+    let assets = &ctx.assets;
+    // Resource packs that ship their own colormaps replace the vanilla biome colours below
+    let (tinted, colormap) = match block.id.as_str() {
+        "grass_block" | "grass" | "tall_grass" | "fern" | "large_fern" | "potted_fern"
+        | "sugar_cane" => (true, assets.colormap("grass")),
+        "oak_leaves" | "jungle_leaves" | "acacia_leaves" | "dark_oak_leaves" | "vines" => {
+            (true, assets.colormap("foliage"))
+        }
+        "water" => (true, None),
+        _ => (false, None),
+    };
+    let color = match colormap.zip(biome_climate(biome_name(&block.biome))) {
+        Some((colormap, (temperature, downfall))) => {
+            // Same lookup the game uses, downfall is scaled by temperature so the map is a triangle
+            let temperature = temperature.clamp(0.0, 1.0);
+            let downfall = downfall.clamp(0.0, 1.0) * temperature;
+            let x = ((1.0 - temperature) * (colormap.width() - 1) as f32) as u32;
+            let y = ((1.0 - downfall) * (colormap.height() - 1) as f32) as u32;
+            let pixel = colormap.get_pixel(x, y);
+            Some(image::Rgb([pixel[0], pixel[1], pixel[2]]))
+        }
+        None => vanilla_color(&block),
+    };
+    if tinted && color.is_none() {
+        diagnostics(ctx).unknown_biome(&block.biome, position);
+    }
+    if let Some(c) = color {
+        for dim_x in 0..block_img.dimensions().0 {
+            for dim_y in 0..block_img.dimensions().1 {
+                let pixel: &mut Rgba<u8> = block_img.get_pixel_mut(dim_x, dim_y);
+                let percent = pixel.0[0] as f32 / 256.0;
+                let channels = pixel.channels_mut();
+                channels[0] = (c.0[0] as f32 * percent) as u8;
+                channels[1] = (c.0[1] as f32 * percent) as u8;
+                channels[2] = (c.0[2] as f32 * percent) as u8;
+            }
+        }
+    }
 }
 
-fn merge_colors(block: Block, chunk: &Chunk, block_img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) {
-    let color = match block.id.as_str() {
+fn vanilla_color(block: &Block) -> Option<image::Rgb<u8>> {
+    match block.id.as_str() {
         "grass_block" | "grass" | "tall_grass" | "fern" | "large_fern" | "potted_fern"
-        | "sugar_cane" => match &block.biome.as_str()[10..] {
+        | "sugar_cane" => match biome_name(&block.biome) {
             "badlands" | "wooded_badlands" | "eroded_badlands" => Some(image::Rgb([144, 129, 77])),
             "desert" | "savanna" | "savanna_plateau" | "windswept_savanna" | "nether_wastes"
             | "soul_sand_valley" | "crimson_forest" | "warped_forest" | "basalt_deltas" => {
@@ -158,13 +547,10 @@ fn merge_colors(block: Block, chunk: &Chunk, block_img: &mut ImageBuffer<Rgba<u8
             | "grove" | "snowy_slopes" | "frozen_peaks" | "jagged_peaks" => {
                 Some(image::Rgb([128, 180, 151]))
             }
-            _ => {
-                println!("{}: {}", block.id, block.biome);
-                None
-            }
+            _ => None,
         },
         "oak_leaves" | "jungle_leaves" | "acacia_leaves" | "dark_oak_leaves" | "vines" => {
-            match &block.biome.as_str()[10..] {
+            match biome_name(&block.biome) {
                 "badlands" | "wooded_badlands" | "eroded_badlands" => {
                     Some(image::Rgb([158, 128, 77]))
                 }
@@ -212,7 +598,7 @@ fn merge_colors(block: Block, chunk: &Chunk, block_img: &mut ImageBuffer<Rgba<u8
                 _ => None,
             }
         }
-        "water" => match &block.biome.as_str()[10..] {
+        "water" => match biome_name(&block.biome) {
             "badlands"
             | "bamboo_jungle"
             | "basalt_deltas"
@@ -277,218 +663,268 @@ fn merge_colors(block: Block, chunk: &Chunk, block_img: &mut ImageBuffer<Rgba<u8
         "spruce_leaves" => Some(image::Rgb([97, 153, 97])),
         "birch_leaves" => Some(image::Rgb([128, 167, 85])),
         _ => None,
-    };
-    match color {
-        Some(c) => {
-            for dim_x in 0..block_img.dimensions().0 {
-                for dim_y in 0..block_img.dimensions().1 {
-                    let pixel: &mut Rgba<u8> = block_img.get_pixel_mut(dim_x, dim_y);
-                    let percent = pixel.0[0] as f32 / 256 as f32;
-                    let channels = pixel.channels_mut();
-                    channels[0] = (c.0[0] as f32 * percent) as u8;
-                    channels[1] = (c.0[1] as f32 * percent) as u8;
-                    channels[2] = (c.0[2] as f32 * percent) as u8;
-                }
-            }
+    }
+}
+
+// Temperature and downfall of each biome, used to look up colours in the grass and foliage colormaps
+fn biome_climate(biome: &str) -> Option<(f32, f32)> {
+    match biome {
+        "desert" | "savanna" | "savanna_plateau" | "windswept_savanna" | "badlands"
+        | "wooded_badlands" | "eroded_badlands" | "nether_wastes" | "soul_sand_valley"
+        | "crimson_forest" | "warped_forest" | "basalt_deltas" => Some((2.0, 0.0)),
+        "plains" | "sunflower_plains" | "beach" | "dripstone_caves" | "deep_dark" => {
+            Some((0.8, 0.4))
         }
-        None => (),
+        "swamp" | "mangrove_swamp" => Some((0.8, 0.9)),
+        "forest" | "flower_forest" | "dark_forest" => Some((0.7, 0.8)),
+        "birch_forest" | "old_growth_birch_forest" => Some((0.6, 0.6)),
+        "taiga" | "old_growth_spruce_taiga" => Some((0.25, 0.8)),
+        "old_growth_pine_taiga" => Some((0.3, 0.8)),
+        "snowy_taiga" => Some((-0.5, 0.4)),
+        "snowy_plains" | "ice_spikes" | "frozen_river" | "frozen_ocean" => Some((0.0, 0.5)),
+        "windswept_hills" | "windswept_gravelly_hills" | "windswept_forest" | "stony_shore" => {
+            Some((0.2, 0.3))
+        }
+        "jungle" | "bamboo_jungle" => Some((0.95, 0.9)),
+        "sparse_jungle" => Some((0.95, 0.8)),
+        "meadow" | "cherry_grove" => Some((0.5, 0.8)),
+        "grove" => Some((-0.2, 0.8)),
+        "snowy_slopes" => Some((-0.3, 0.9)),
+        "frozen_peaks" | "jagged_peaks" => Some((-0.7, 0.9)),
+        "stony_peaks" => Some((1.0, 0.3)),
+        "snowy_beach" => Some((0.05, 0.3)),
+        "mushroom_fields" => Some((0.9, 1.0)),
+        "ocean"
+        | "deep_ocean"
+        | "warm_ocean"
+        | "lukewarm_ocean"
+        | "deep_lukewarm_ocean"
+        | "cold_ocean"
+        | "deep_cold_ocean"
+        | "deep_frozen_ocean"
+        | "river"
+        | "lush_caves"
+        | "the_end"
+        | "small_end_islands"
+        | "end_barrens"
+        | "end_midlands"
+        | "end_highlands"
+        | "the_void" => Some((0.5, 0.5)),
+        _ => None,
     }
 }
 
 fn merge_background(
     block_img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    chunk: &Chunk,
-    x: i32,
-    y: i32,
-    z: i32,
-    region_file_name: &String,
-    region_path: &PathBuf,
-    texture_cache: Arc<Mutex<HashMap<String, DynamicImage>>>,
-) {
-    let mut y = y - 1;
-    let mut below = chunk.get_block(x, y, z);
-    while NON_SOLID.contains(&below.id.as_str()) {
+    position: (i32, i32, i32),
+    ctx: &RenderContext,
+) -> Result<(), RenderError> {
+    let (x, mut y, z) = position;
+    let chunk = match ctx.world.get_chunk(x.div_euclid(16), z.div_euclid(16))? {
+        Some(chunk) => chunk,
+        None => return Ok(()),
+    };
+    y -= 1;
+    let mut below = chunk.get_block(x.rem_euclid(16), y, z.rem_euclid(16));
+    // Columns of water or air all the way down stop at the bottom of the world
+    while NON_SOLID.contains(&below.id.as_str()) && y > ctx.format.min_y() {
         y -= 1;
-        below = chunk.get_block(x, y, z);
-    }
-    let mut background_tex = get_texture(&below, region_file_name, region_path, texture_cache);
-
-    let dims = background_tex.dimensions();
-    if dims.0 > 16 || dims.1 > 16 {
-        background_tex = background_tex.crop(0, 0, 16, 16);
+        below = chunk.get_block(x.rem_euclid(16), y, z.rem_euclid(16));
     }
-
+    let background_tex = get_texture(&below, (x, y, z), ctx)?;
     let mut background_img = background_tex.into_rgba8();
 
-    merge_colors(below, &chunk, &mut background_img);
+    merge_colors(below, (x, y, z), ctx, &mut background_img);
 
     image::imageops::overlay(&mut background_img, block_img, 0, 0);
     *block_img = background_img;
+    Ok(())
 }
 
 fn get_texture(
     b: &Block,
-    region_file_name: &String,
-    region_path: &PathBuf,
-    texture_cache: Arc<Mutex<HashMap<String, DynamicImage>>>,
-) -> DynamicImage {
+    position: (i32, i32, i32),
+    ctx: &RenderContext,
+) -> Result<DynamicImage, RenderError> {
+    let assets = &ctx.assets;
     let water = Block::from_name("minecraft:water".into(), b.coords, None, String::new());
-    let block = if b.id == "bubble_column" { &water } else { &b };
-    let mut cache = texture_cache.lock().unwrap();
-
-    let mut tex = if cache.contains_key(&block.id) {
-        cache.get(&block.id).unwrap().clone()
-    } else if Path::new(&format!(
-        "./assets/minecraft/textures/block/{}.png",
-        block.id
-    ))
-    .exists()
-    {
-        let img = open(format!(
-            "./assets/minecraft/textures/block/{}.png",
-            block.id
-        ))
-        .unwrap();
-        cache.insert(block.id.clone(), img.clone());
-        img
-    } else if Path::new(&format!(
-        "./assets/minecraft/textures/block/{}_top.png",
-        block.id
-    ))
-    .exists()
-    {
-        let img = open(format!(
-            "./assets/minecraft/textures/block/{}_top.png",
-            block.id
-        ))
-        .unwrap();
-        cache.insert(block.id.clone(), img.clone());
-        img
-    } else if Path::new(&format!(
-        "./assets/minecraft/textures/block/{}_still.png",
-        block.id
-    ))
-    .exists()
-    {
-        let img = open(format!(
-            "./assets/minecraft/textures/block/{}_still.png",
-            block.id
-        ))
-        .unwrap();
-        cache.insert(block.id.clone(), img.clone());
-        img
-    } else if Path::new(&format!(
-        "./assets/minecraft/textures/block/{}.png",
-        block.id.split("_").collect::<Vec<&str>>()[0]
-    ))
-    .exists()
-    {
-        let img = open(format!(
-            "./assets/minecraft/textures/block/{}.png",
-            block.id.split("_").collect::<Vec<&str>>()[0]
-        ))
-        .unwrap();
-        cache.insert(block.id.clone(), img.clone());
-        img
-    } else if Path::new(&format!(
-        "./assets/minecraft/textures/block/{}_down_tip.png",
-        block.id
-    ))
-    .exists()
+    let block = if b.id == "bubble_column" { &water } else { b };
+    // Fences take their shape from their neighbours, every shape is cached on its own
+    let connections = if block.id.contains("fence") {
+        Some(models::fence_connections(position, &ctx.world)?)
+    } else {
+        None
+    };
+    let key = match connections {
+        Some(connections) => format!("{}#{}", block.id, connections),
+        None => block.id.clone(),
+    };
+    // Locks are only held for the lookup, decoding happens outside of them so a panic in one chunk cannot poison
+    // them for the rest of the job
+    let cached = ctx
+        .texture_cache
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&key)
+        .cloned();
+    let texture = match cached {
+        Some(texture) => {
+            if texture.fallback {
+                diagnostics(ctx).repeat_fallback(&block.id, position);
+            }
+            texture
+        }
+        None => {
+            let (image, heuristic) = match find_texture(block, connections, ctx)? {
+                Some((image, heuristic)) => (Some(image), heuristic),
+                None => (None, None),
+            };
+            if let Some(heuristic) = heuristic {
+                diagnostics(ctx).fallback(&block.id, heuristic, position);
+            }
+            let texture = CachedTexture {
+                image,
+                fallback: heuristic.is_some(),
+            };
+            ctx.texture_cache
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(key, texture.clone());
+            texture
+        }
+    };
+    let mut tex = match texture.image {
+        Some(img) => img,
+        None => {
+            diagnostics(ctx).missing_texture(&block.id, position);
+            missing_texture(assets.resolution())
+        }
+    };
+
+    // Animation frames are already picked out by the asset loader, anything that does not match the pack's
+    // resolution is scaled so every block covers the same area of the tile
+    let resolution = assets.resolution();
+    if tex.dimensions() != (resolution, resolution) {
+        tex = tex.resize_exact(resolution, resolution, FilterType::Nearest);
+    }
+    Ok(tex)
+}
+
+fn diagnostics(ctx: &RenderContext) -> MutexGuard<'_, Diagnostics> {
+    ctx.diagnostics
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+// Works through the places a block's texture can be along with the heuristic that found it if it is only a guess,
+// `None` when there is nothing to show for it
+fn find_texture(
+    block: &Block,
+    fence_connections: Option<u8>,
+    ctx: &RenderContext,
+) -> Result<Option<(DynamicImage, Option<&'static str>)>, RenderError> {
+    let assets = &ctx.assets;
+    let mut heuristic = None;
+    let img = if let Some(img) = assets
+        .model_top_texture(&block.id)
+        .and_then(|name| load_texture(assets, &name).ok())
     {
-        let img = open(format!(
-            "./assets/minecraft/textures/block/{}_down_tip.png",
-            block.id
-        ))
-        .unwrap();
-        cache.insert(block.id.clone(), img.clone());
         img
-    } else if fs::read_dir("./assets/minecraft/textures/block/")
-        .unwrap()
-        .any(|f| {
-            let name = f.unwrap().file_name();
-            let sections = name
-                .to_str()
-                .unwrap()
-                .split(block.id.as_str())
-                .collect::<Vec<&str>>();
-            sections[0] == "" && sections[1].len() == 6
-        })
-    {
-        println!("Found variant block: {}", block.id);
-        let mut variants = fs::read_dir("./assets/minecraft/textures/block/")
-            .unwrap()
-            .map(|f| f.unwrap())
-            .filter(|f| {
-                let name = f.file_name();
-                let sections = name
-                    .to_str()
-                    .unwrap()
-                    .split(block.id.as_str())
-                    .collect::<Vec<&str>>();
-                sections[0] == "" && sections[1].len() == 6
+    } else if assets.block_texture_exists(&block.id) {
+        load_texture(assets, &block.id)?
+    } else if assets.block_texture_exists(&format!("{}_top", block.id)) {
+        load_texture(assets, &format!("{}_top", block.id))?
+    } else if assets.block_texture_exists(&format!("{}_still", block.id)) {
+        load_texture(assets, &format!("{}_still", block.id))?
+    } else if assets.block_texture_exists(block.id.split("_").collect::<Vec<&str>>()[0]) {
+        heuristic = Some("first word of id");
+        load_texture(assets, block.id.split("_").collect::<Vec<&str>>()[0])?
+    } else if assets.block_texture_exists(&format!("{}_down_tip", block.id)) {
+        load_texture(assets, &format!("{}_down_tip", block.id))?
+    } else if assets.list("minecraft/textures/block").iter().any(|name| {
+        let sections = name.split(block.id.as_str()).collect::<Vec<&str>>();
+        sections[0].is_empty() && sections[1].len() == 6
+    }) {
+        heuristic = Some("highest numbered variant");
+        let mut variants = assets
+            .list("minecraft/textures/block")
+            .into_iter()
+            .filter(|name| {
+                let sections = name.split(block.id.as_str()).collect::<Vec<&str>>();
+                sections[0].is_empty() && sections[1].len() == 6
             })
-            .map(|f| f.path())
+            .map(PathBuf::from)
             .collect::<Vec<PathBuf>>();
-        for variant in variants.clone() {
-            println!("{}", variant.file_stem().unwrap().to_str().unwrap());
-        }
-        variants.sort_by(|a, b| {
-            // Sorts by the last character of the file stem (name without extension)
-            a.file_stem()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .chars()
-                .next_back()
-                .unwrap()
-                .to_digit(10)
-                .unwrap()
-                .partial_cmp(
-                    &b.file_stem()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .chars()
-                        .next_back()
-                        .unwrap()
-                        .to_digit(10)
-                        .unwrap(),
-                )
-                .unwrap()
+        // Sorts by the last character of the file stem (name without extension)
+        variants.sort_by_key(|path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.chars().next_back())
+                .and_then(|last| last.to_digit(10))
+                .unwrap_or(0)
         });
-        let img = open(format!(
-            "./assets/minecraft/textures/block/{}.png",
-            variants
-                .last()
-                .unwrap()
-                .file_stem()
-                .unwrap()
-                .to_str()
-                .unwrap()
-        ))
-        .unwrap();
-        cache.insert(block.id.clone(), img.clone());
-        img
-    } else if block.id.contains("fence") {
-        let img = models::generate_fence_texture(block, region_file_name, region_path).unwrap();
-        cache.insert(block.id.clone(), img.clone());
-        img
-    } else {
-        println!("found block : {}", block.id);
-        let mut tex = RgbaImage::new(16, 16);
-        for x in 0..16 {
-            for z in 0..16 {
-                tex.put_pixel(x, z, Rgba([0, 0, 0, 1]))
-            }
+        match variants
+            .last()
+            .and_then(|path| path.file_stem())
+            .and_then(|stem| stem.to_str())
+        {
+            Some(name) => load_texture(assets, name)?,
+            None => return Ok(None),
         }
-        DynamicImage::ImageRgba8(tex)
+    } else if let Some(connections) = fence_connections {
+        models::generate_fence_texture(block, connections, assets)?
+    } else {
+        return Ok(None);
     };
+    Ok(Some((img, heuristic)))
+}
+
+fn load_texture(assets: &Assets, name: &str) -> Result<DynamicImage, RenderError> {
+    assets
+        .block_texture(name)
+        .ok_or_else(|| RenderError::MissingTexture(name.to_string()))
+}
+
+// Strips the namespace from a biome id, old chunks can have an empty biome
+fn biome_name(biome: &str) -> &str {
+    biome.strip_prefix("minecraft:").unwrap_or(biome)
+}
+
+// The same magenta and black checker the game uses, hard to miss on a map
+fn missing_texture(resolution: u32) -> DynamicImage {
+    let half = (resolution / 2).max(1);
+    let mut tex = RgbaImage::new(resolution, resolution);
+    for x in 0..resolution {
+        for z in 0..resolution {
+            let pixel = if (x / half + z / half) & 1 == 0 {
+                Rgba([248, 0, 248, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            };
+            tex.put_pixel(x, z, pixel);
+        }
+    }
+    DynamicImage::ImageRgba8(tex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_full, is_renderable};
+
+    #[test]
+    fn full_chunks_are_always_rendered() {
+        assert!(is_full("full"));
+        assert!(is_full("minecraft:full"));
+        assert!(is_renderable("minecraft:full", false));
+        assert!(is_renderable("full", true));
+    }
 
-    // If the block texture is greater than 16x16 then we only use a single 16x16 section, this is the case for animated blocks such as water
-    let dims = tex.dimensions();
-    if dims.0 > 16 || dims.1 > 16 {
-        tex = tex.crop(0, 0, 16, 16);
+    #[test]
+    fn proto_chunks_need_a_surface_and_the_setting() {
+        assert!(!is_renderable("minecraft:features", false));
+        assert!(is_renderable("minecraft:features", true));
+        assert!(is_renderable("heightmaps", true));
+        assert!(!is_renderable("minecraft:noise", true));
+        assert!(!is_renderable("minecraft:empty", true));
     }
-    tex
 }
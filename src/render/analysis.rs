@@ -0,0 +1,134 @@
+use image::{Rgb, RgbaImage};
+use nbt::{Blob, Value};
+use simple_anvil::chunk::Chunk;
+
+use super::{
+    biomes::biome_color,
+    mode::{HeightSource, RenderMode},
+    RenderContext,
+};
+use crate::tags::{byte, byte_array, list};
+
+// Top of the build limit, heights are spread over the ramp from the bottom of the world up to here
+pub const MAX_Y: i32 = 320;
+// Columns whose light was never calculated
+pub const UNKNOWN: Rgb<u8> = Rgb([80, 80, 80]);
+// Monsters spawn where block light is 0
+pub const SPAWNABLE: Rgb<u8> = Rgb([220, 0, 0]);
+
+// Sky light is this many levels lower at midnight, the same drop the game applies
+const NIGHT_SKY_DROP: u8 = 11;
+// Even unlit blocks are faintly visible at night
+const NIGHT_MIN_BRIGHTNESS: f32 = 0.08;
+
+// Blue through green and yellow to red
+pub fn ramp(t: f32) -> Rgb<u8> {
+    let t = t.clamp(0.0, 1.0);
+    let (r, g, b) = if t < 0.5 {
+        (0.0, t * 2.0, 1.0 - t * 2.0)
+    } else {
+        (1.0, 2.0 - t * 2.0, 0.0)
+    };
+    Rgb([(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8])
+}
+
+pub fn height_color(y: i32, min_y: i32) -> Rgb<u8> {
+    ramp((y - min_y) as f32 / (MAX_Y - min_y) as f32)
+}
+
+pub fn block_light_color(level: u8) -> Rgb<u8> {
+    match level {
+        0 => SPAWNABLE,
+        level => Rgb([level * 17, level * 15, level * 6]),
+    }
+}
+
+pub fn sky_light_color(level: u8) -> Rgb<u8> {
+    Rgb([level * 12, level * 14, level * 17])
+}
+
+// Light is stored per section as one 4 bit value per block, `None` when the section has no light data
+fn light_level(chunk: &Blob, name: &str, (x, y, z): (usize, i32, usize)) -> Option<u8> {
+    let section_y = y.div_euclid(16) as i8;
+    let section = list(chunk, "sections")?
+        .iter()
+        .find_map(|section| match section {
+            Value::Compound(section) if byte(section, "Y") == Some(section_y) => Some(section),
+            _ => None,
+        })?;
+    let index = y.rem_euclid(16) as usize * 256 + z * 16 + x;
+    let value = *byte_array(section, name)?.get(index / 2)? as u8;
+    Some(if index & 1 == 0 {
+        value & 0xF
+    } else {
+        value >> 4
+    })
+}
+
+// Colour of a column in the analytic render modes, `None` in terrain mode. `light` is the raw chunk NBT, only read
+// for the light modes
+pub fn column_color(
+    ctx: &RenderContext,
+    chunk: &Chunk,
+    light: Option<&Blob>,
+    (x, z): (usize, usize),
+    surface: i32,
+    ocean_floor: i32,
+) -> Option<Rgb<u8>> {
+    let min_y = ctx.format.min_y();
+    // The first block above the surface, where light matters
+    let above = (x, surface + 1, z);
+    Some(match ctx.mode {
+        RenderMode::Terrain | RenderMode::Night => return None,
+        RenderMode::Biomes(level) => {
+            let y = level.unwrap_or(surface).max(min_y);
+            biome_color(&chunk.get_block(x as i32, y, z as i32).biome)
+        }
+        RenderMode::Height(HeightSource::WorldSurface) => height_color(surface, min_y),
+        RenderMode::Height(HeightSource::OceanFloor) => height_color(ocean_floor, min_y),
+        RenderMode::BlockLight => light
+            .and_then(|light| light_level(light, "BlockLight", above))
+            .map(block_light_color)
+            .unwrap_or(UNKNOWN),
+        RenderMode::SkyLight => light
+            .and_then(|light| light_level(light, "SkyLight", above))
+            .map(sky_light_color)
+            .unwrap_or(UNKNOWN),
+    })
+}
+
+// Darkens a terrain block for the night render mode by the light above it, moonlight is a little blue and block
+// light a little orange like torches
+pub fn shade_night(
+    block_img: &mut RgbaImage,
+    light: Option<&Blob>,
+    (x, z): (usize, usize),
+    surface: i32,
+) {
+    let above = (x, surface + 1, z);
+    // Columns without light data are treated as open sky with nothing lit
+    let sky = light
+        .and_then(|light| light_level(light, "SkyLight", above))
+        .unwrap_or(15)
+        .saturating_sub(NIGHT_SKY_DROP);
+    let block = light
+        .and_then(|light| light_level(light, "BlockLight", above))
+        .unwrap_or(0);
+    // The game's curve from light level to brightness, dim levels fall off quickly
+    let brightness = |level: u8| {
+        let b = level as f32 / 15.0;
+        b / (4.0 - 3.0 * b)
+    };
+    let (sky, block) = (brightness(sky), brightness(block));
+    let tint = [
+        sky * 0.8 + block,
+        sky * 0.85 + block * 0.85,
+        sky + block * 0.6,
+    ];
+    for pixel in block_img.pixels_mut() {
+        // Alpha is left alone, only the three colour channels are zipped
+        for (channel, tint) in pixel.0.iter_mut().zip(tint) {
+            *channel = (*channel as f32 * (NIGHT_MIN_BRIGHTNESS + tint).min(1.0)) as u8;
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use image::Rgb;
+
+// Colours of the vanilla biomes on the biome map, close to the ones seed map tools use so they are familiar
+pub const BIOME_COLORS: [(&str, [u8; 3]); 64] = [
+    ("ocean", [0, 0, 112]),
+    ("deep_ocean", [0, 0, 48]),
+    ("warm_ocean", [0, 0, 172]),
+    ("lukewarm_ocean", [0, 0, 144]),
+    ("deep_lukewarm_ocean", [0, 0, 64]),
+    ("cold_ocean", [32, 32, 112]),
+    ("deep_cold_ocean", [32, 32, 56]),
+    ("frozen_ocean", [112, 112, 214]),
+    ("deep_frozen_ocean", [64, 64, 144]),
+    ("river", [0, 0, 255]),
+    ("frozen_river", [160, 160, 255]),
+    ("beach", [250, 222, 85]),
+    ("snowy_beach", [250, 240, 192]),
+    ("stony_shore", [162, 162, 132]),
+    ("plains", [141, 179, 96]),
+    ("sunflower_plains", [181, 219, 136]),
+    ("snowy_plains", [255, 255, 255]),
+    ("ice_spikes", [180, 220, 220]),
+    ("desert", [250, 148, 24]),
+    ("swamp", [7, 249, 178]),
+    ("mangrove_swamp", [44, 204, 142]),
+    ("forest", [5, 102, 33]),
+    ("flower_forest", [45, 142, 73]),
+    ("birch_forest", [48, 116, 68]),
+    ("old_growth_birch_forest", [88, 156, 108]),
+    ("dark_forest", [64, 81, 26]),
+    ("cherry_grove", [255, 145, 200]),
+    ("taiga", [11, 102, 89]),
+    ("snowy_taiga", [49, 85, 74]),
+    ("old_growth_pine_taiga", [89, 102, 81]),
+    ("old_growth_spruce_taiga", [129, 142, 121]),
+    ("jungle", [83, 123, 9]),
+    ("sparse_jungle", [98, 139, 23]),
+    ("bamboo_jungle", [118, 142, 20]),
+    ("savanna", [189, 178, 95]),
+    ("savanna_plateau", [167, 157, 100]),
+    ("windswept_savanna", [229, 218, 135]),
+    ("badlands", [217, 69, 21]),
+    ("wooded_badlands", [176, 151, 101]),
+    ("eroded_badlands", [255, 109, 61]),
+    ("windswept_hills", [96, 96, 96]),
+    ("windswept_forest", [80, 112, 80]),
+    ("windswept_gravelly_hills", [136, 136, 136]),
+    ("meadow", [96, 164, 69]),
+    ("grove", [71, 114, 108]),
+    ("snowy_slopes", [196, 196, 196]),
+    ("frozen_peaks", [160, 160, 160]),
+    ("jagged_peaks", [220, 220, 200]),
+    ("stony_peaks", [123, 143, 116]),
+    ("mushroom_fields", [255, 0, 255]),
+    ("dripstone_caves", [134, 96, 67]),
+    ("lush_caves", [40, 200, 40]),
+    ("deep_dark", [20, 40, 60]),
+    ("nether_wastes", [191, 59, 59]),
+    ("soul_sand_valley", [94, 56, 48]),
+    ("crimson_forest", [221, 8, 8]),
+    ("warped_forest", [73, 144, 123]),
+    ("basalt_deltas", [64, 54, 54]),
+    ("the_end", [128, 128, 255]),
+    ("small_end_islands", [75, 75, 171]),
+    ("end_midlands", [201, 201, 89]),
+    ("end_highlands", [181, 181, 54]),
+    ("end_barrens", [112, 112, 204]),
+    ("the_void", [0, 0, 0]),
+];
+
+// Biomes from mods or newer versions get a stable colour made up from their name
+pub fn biome_color(biome: &str) -> Rgb<u8> {
+    let name = biome.strip_prefix("minecraft:").unwrap_or(biome);
+    match BIOME_COLORS.iter().find(|(known, _)| *known == name) {
+        Some((_, color)) => Rgb(*color),
+        None => {
+            let hash = name.bytes().fold(2166136261u32, |hash, b| {
+                (hash ^ b as u32).wrapping_mul(16777619)
+            });
+            Rgb([(hash >> 16) as u8, (hash >> 8) as u8, hash as u8])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use image::Rgb;
+
+    use super::{biome_color, BIOME_COLORS};
+
+    #[test]
+    fn known_biomes_use_their_colour() {
+        assert_eq!(biome_color("minecraft:plains"), Rgb([141, 179, 96]));
+        assert_eq!(biome_color("plains"), Rgb([141, 179, 96]));
+        assert_eq!(biome_color("minecraft:the_void"), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn unknown_biomes_get_a_stable_colour() {
+        let color = biome_color("terralith:moonlight_valley");
+        assert_eq!(color, biome_color("terralith:moonlight_valley"));
+        assert_ne!(color, biome_color("terralith:volcanic_peaks"));
+    }
+
+    #[test]
+    fn every_biome_is_listed_once() {
+        let names = BIOME_COLORS
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<HashSet<&str>>();
+        assert_eq!(names.len(), BIOME_COLORS.len());
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+// How many coordinates to keep for each problem, enough to go and look at it in game
+const SAMPLES: usize = 5;
+
+#[derive(Default)]
+pub struct Occurrences {
+    pub count: u32,
+    pub samples: Vec<(i32, i32, i32)>,
+}
+
+impl Occurrences {
+    fn record(&mut self, position: (i32, i32, i32)) {
+        self.count += 1;
+        if self.samples.len() < SAMPLES {
+            self.samples.push(position);
+        }
+    }
+}
+
+// Everything the renderer had to guess at or give up on during a single render job
+#[derive(Default)]
+pub struct Diagnostics {
+    pub missing_textures: BTreeMap<String, Occurrences>,
+    pub unknown_biomes: BTreeMap<String, Occurrences>,
+    // Keyed by block id along with the heuristic that found its texture, looked up on every cached texture hit
+    pub fallbacks: HashMap<String, (&'static str, Occurrences)>,
+}
+
+impl Diagnostics {
+    pub fn missing_texture(&mut self, block_id: &str, position: (i32, i32, i32)) {
+        self.missing_textures
+            .entry(block_id.to_string())
+            .or_default()
+            .record(position);
+    }
+
+    pub fn unknown_biome(&mut self, biome: &str, position: (i32, i32, i32)) {
+        self.unknown_biomes
+            .entry(biome.to_string())
+            .or_default()
+            .record(position);
+    }
+
+    pub fn fallback(&mut self, block_id: &str, heuristic: &'static str, position: (i32, i32, i32)) {
+        self.fallbacks
+            .entry(block_id.to_string())
+            .or_insert_with(|| (heuristic, Occurrences::default()))
+            .1
+            .record(position);
+    }
+
+    // Textures are cached by block id so heuristics only run once, later hits of the same block still count
+    pub fn repeat_fallback(&mut self, block_id: &str, position: (i32, i32, i32)) {
+        if let Some((_, occurrences)) = self.fallbacks.get_mut(block_id) {
+            occurrences.record(position);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing_textures.is_empty()
+            && self.unknown_biomes.is_empty()
+            && self.fallbacks.is_empty()
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        if self.is_empty() {
+            report.push_str("No missing textures or unknown biomes\n");
+            return report;
+        }
+        section(
+            &mut report,
+            "Missing textures",
+            self.missing_textures.iter(),
+        );
+        section(&mut report, "Unknown biomes", self.unknown_biomes.iter());
+        section(
+            &mut report,
+            "Fallback textures",
+            self.fallbacks
+                .iter()
+                .map(|(id, (heuristic, o))| (format!("{} ({})", id, heuristic), o)),
+        );
+        report
+    }
+}
+
+fn section<'a, K: std::fmt::Display, I: Iterator<Item = (K, &'a Occurrences)>>(
+    report: &mut String,
+    title: &str,
+    entries: I,
+) {
+    let mut entries = entries.collect::<Vec<(K, &Occurrences)>>();
+    if entries.is_empty() {
+        return;
+    }
+    // Most frequent first, ties by name so the report does not change order from one render to the next
+    entries.sort_by(|a, b| {
+        b.1.count
+            .cmp(&a.1.count)
+            .then_with(|| a.0.to_string().cmp(&b.0.to_string()))
+    });
+    writeln!(report, "{}:", title).unwrap();
+    for (name, occurrences) in entries {
+        let samples = occurrences
+            .samples
+            .iter()
+            .map(|(x, y, z)| format!("{} {} {}", x, y, z))
+            .collect::<Vec<String>>()
+            .join(", ");
+        writeln!(report, "  {} x{} at {}", name, occurrences.count, samples).unwrap();
+    }
+}
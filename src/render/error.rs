@@ -0,0 +1,76 @@
+use std::{
+    any::Any,
+    fmt::{self, Display},
+    io,
+    path::PathBuf,
+};
+
+use image::ImageError;
+
+#[derive(Debug)]
+pub enum RenderError {
+    MissingRegionDirectory(PathBuf),
+    CorruptRegion((i32, i32), String),
+    CorruptChunk((i32, i32), String),
+    MissingHeightmap((i32, i32)),
+    MissingTexture(String),
+    InvalidTileName(String),
+    UnsupportedChunkFormat(&'static str),
+    Image(ImageError),
+    Io(io::Error),
+}
+
+impl RenderError {
+    // simple_anvil panics on data it cannot parse, this turns the panic payload into an error message
+    pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+        match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "unknown panic".to_string(),
+            },
+        }
+    }
+}
+
+impl Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::MissingRegionDirectory(path) => {
+                write!(f, "no region directory at {}", path.display())
+            }
+            RenderError::CorruptRegion((x, z), reason) => {
+                write!(f, "region r.{}.{}.mca is corrupt: {}", x, z, reason)
+            }
+            RenderError::CorruptChunk((x, z), reason) => {
+                write!(f, "chunk {} {} is corrupt: {}", x, z, reason)
+            }
+            RenderError::MissingHeightmap((x, z)) => {
+                write!(f, "chunk {} {} has no heightmaps", x, z)
+            }
+            RenderError::MissingTexture(name) => write!(f, "texture {} does not exist", name),
+            RenderError::InvalidTileName(name) => write!(f, "cannot parse tile name {}", name),
+            RenderError::UnsupportedChunkFormat(format) => write!(
+                f,
+                "chunks from {} worlds are not supported, open the world in 1.18 or later to upgrade it",
+                format
+            ),
+            RenderError::Image(e) => write!(f, "image error: {}", e),
+            RenderError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<io::Error> for RenderError {
+    fn from(e: io::Error) -> Self {
+        RenderError::Io(e)
+    }
+}
+
+impl From<ImageError> for RenderError {
+    fn from(e: ImageError) -> Self {
+        RenderError::Image(e)
+    }
+}
@@ -0,0 +1,56 @@
+// What the tiles show, every mode keeps its own tiles so switching back and forth does not rerender
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum RenderMode {
+    #[default]
+    Terrain,
+    // Colours columns by biome at the surface, or at a fixed height to see the 3D biomes underground
+    Biomes(Option<i32>),
+    Height(HeightSource),
+    // Block light just above the surface, where mobs would spawn
+    BlockLight,
+    SkyLight,
+    // Terrain as it looks at midnight, dark except where blocks give off light
+    Night,
+}
+
+// Which of the chunk's heightmaps a height map is drawn from
+#[derive(Clone, Copy, PartialEq)]
+pub enum HeightSource {
+    WorldSurface,
+    OceanFloor,
+}
+
+impl RenderMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RenderMode::Terrain => "Terrain",
+            RenderMode::Biomes(_) => "Biomes",
+            RenderMode::Height(_) => "Height",
+            RenderMode::BlockLight => "Block light",
+            RenderMode::SkyLight => "Sky light",
+            RenderMode::Night => "Night",
+        }
+    }
+
+    // Appended to the save name for the folder the mode's tiles are kept in, terrain tiles keep the plain name
+    pub fn suffix(&self) -> String {
+        match self {
+            RenderMode::Terrain => String::new(),
+            RenderMode::Biomes(None) => ".biomes".to_string(),
+            RenderMode::Biomes(Some(y)) => format!(".biomes_y{}", y),
+            RenderMode::Height(HeightSource::WorldSurface) => ".height".to_string(),
+            RenderMode::Height(HeightSource::OceanFloor) => ".ocean_floor".to_string(),
+            RenderMode::BlockLight => ".block_light".to_string(),
+            RenderMode::SkyLight => ".sky_light".to_string(),
+            RenderMode::Night => ".night".to_string(),
+        }
+    }
+
+    // Light modes need light data simple_anvil does not read
+    pub fn needs_light(&self) -> bool {
+        matches!(
+            self,
+            RenderMode::BlockLight | RenderMode::SkyLight | RenderMode::Night
+        )
+    }
+}
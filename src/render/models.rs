@@ -1,135 +1,102 @@
-use std::path::PathBuf;
+use std::ops::Range;
 
-use image::{open, DynamicImage, GenericImageView, ImageResult, RgbaImage};
-use simple_anvil::{block::Block, region::Region};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use simple_anvil::block::Block;
 
-use crate::render::NON_SOLID;
+use crate::{
+    assets::Assets,
+    render::{error::RenderError, NON_SOLID},
+    world::WorldView,
+};
 
-pub fn generate_fence_texture(
-    block: &Block,
-    region_file_name: &String,
-    region_path: &PathBuf,
-) -> ImageResult<DynamicImage> {
-    let parts = block.id.split("_fence").collect::<Vec<&str>>();
-    let wood_type = parts.first().unwrap();
-    let mut base = RgbaImage::new(16, 16);
-    println!(
-        "Trying to find base wood texture for {}. {}_planks.png?",
-        block.id, wood_type
-    );
-    let wood_tex = open(format!(
-        "./assets/minecraft/textures/block/{}_planks.png",
-        wood_type
-    ))
-    .unwrap();
+// Which sides of a fence connect to a neighbour, one bit each for west, east, north and south
+pub fn fence_connections(position: (i32, i32, i32), world: &WorldView) -> Result<u8, RenderError> {
+    let (x, y, z) = position;
+    let block_coords = [(x - 1, y, z), (x + 1, y, z), (x, y, z - 1), (x, y, z + 1)];
 
-    // Fill center 4x4
-    for x in 6..10 {
-        for z in 6..10 {
-            base.put_pixel(x, z, wood_tex.get_pixel(x, z));
+    // For each adjacent block check if the block is solid, if it is then the fence connects towards it
+    let mut connections = 0;
+    for (dir, (x, y, z)) in block_coords.into_iter().enumerate() {
+        let adj = match world.get_block(x, y, z)? {
+            Some(adj) => adj,
+            None => continue, // Neighbour chunk has not been generated
+        };
+        if !NON_SOLID.contains(&adj.id.as_str()) {
+            connections |= 1 << dir;
         }
     }
+    Ok(connections)
+}
 
-    let (x, y, z) = block.coords.unwrap();
-    let block_coords = [
-        (x - 1, y, z, 0),
-        (x + 1, y, z, 1),
-        (x, y, z - 1, 2),
-        (x, y, z + 1, 3),
-    ];
-
-    let reg_parts = region_file_name.split(".").collect::<Vec<&str>>();
-    let reg_coords = (
-        reg_parts[1].parse::<i32>().unwrap(),
-        reg_parts[2].parse::<i32>().unwrap(),
-    );
-
-    // For each adjacent block check if the block is solid, if it is then we draw the non post section of the fence towards the adjacent block
-    for coord in block_coords {
-        let (x, y, z, dir) = coord;
-        let region_folder = region_path.parent().unwrap();
-        // println!("{} | {}", region_file_name, region_folder.to_str().unwrap());
-        let adj = if x < 0 {
-            let region = Region::from_file(format!(
-                "{}\\r.{}.{}.mca",
-                region_folder.to_str().unwrap(),
-                reg_coords.0 - 1,
-                reg_coords.1
-            ));
-            region.get_block(x + 512, y, z).unwrap()
-        } else if x >= 512 {
-            let region = Region::from_file(format!(
-                "{}\\r.{}.{}.mca",
-                region_folder.to_str().unwrap(),
-                reg_coords.0 + 1,
-                reg_coords.1
-            ));
-            region.get_block(x - 512, y, z).unwrap()
-        } else if z < 0 {
-            let region = Region::from_file(format!(
-                "{}\\r.{}.{}.mca",
-                region_folder.to_str().unwrap(),
-                reg_coords.0,
-                reg_coords.1 - 1
-            ));
-            region.get_block(x, y, z + 512).unwrap()
-        } else if z >= 512 {
-            let region = Region::from_file(format!(
-                "{}\\r.{}.{}.mca",
-                region_folder.to_str().unwrap(),
-                reg_coords.0,
-                reg_coords.1 + 1
-            ));
-            region.get_block(x, y, z - 512).unwrap()
-        } else {
-            // The block is still within the original region
-            let region = Region::from_file(format!(
-                "{}\\{}",
-                region_folder.to_str().unwrap(),
-                region_file_name.to_string()
-            ));
-            region.get_block(x, y, z).unwrap()
-        };
-
-        // Connect to all non solid blocks
-        if !NON_SOLID.contains(&adj.id.as_str()) {
-            match dir {
-                0 => {
-                    for x in 7..9 {
-                        for z in 9..16 {
-                            base.put_pixel(x, z, wood_tex.get_pixel(x, z));
-                        }
-                    }
-                }
-                1 => {
-                    for x in 7..9 {
-                        for z in 0..7 {
-                            base.put_pixel(x, z, wood_tex.get_pixel(x, z));
-                        }
-                    }
-                }
-                2 => {
-                    for x in 9..16 {
-                        for z in 7..9 {
-                            base.put_pixel(x, z, wood_tex.get_pixel(x, z));
-                        }
-                    }
-                }
-                3 => {
-                    for x in 0..7 {
-                        for z in 7..9 {
-                            base.put_pixel(x, z, wood_tex.get_pixel(x, z));
-                        }
-                    }
+pub fn generate_fence_texture(
+    block: &Block,
+    connections: u8,
+    assets: &Assets,
+) -> Result<DynamicImage, RenderError> {
+    let wood_type = block.id.split("_fence").next().unwrap_or(&block.id);
+    let planks = format!("{}_planks", wood_type);
+    let wood_tex = assets
+        .block_texture(&planks)
+        .ok_or(RenderError::MissingTexture(planks))?;
+    // The fence shape is laid out on a 16x16 grid and mapped onto however many pixels the pack uses
+    let size = wood_tex.width().min(wood_tex.height());
+    let mut base = RgbaImage::new(size, size);
+    let mut fill = |xs: Range<u32>, zs: Range<u32>| {
+        for x in 0..size {
+            for z in 0..size {
+                if xs.contains(&(x * 16 / size)) && zs.contains(&(z * 16 / size)) {
+                    base.put_pixel(x, z, wood_tex.get_pixel(x, z));
                 }
-                _ => unreachable!("This is hardcoded and should be unreachable"),
             }
         }
+    };
+
+    // Fill center 4x4
+    fill(6..10, 6..10);
+
+    // Draw the non post section of the fence towards every connected side
+    let arms = [(7..9, 9..16), (7..9, 0..7), (9..16, 7..9), (0..7, 7..9)];
+    for (dir, (xs, zs)) in arms.into_iter().enumerate() {
+        if connections & (1 << dir) != 0 {
+            fill(xs, zs);
+        }
     }
 
     Ok(DynamicImage::ImageRgba8(base))
 }
 
-pub fn generate_chest_texture() {
-    todo!("not yet");
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use image::{GenericImageView, Rgba, RgbaImage};
+    use simple_anvil::block::Block;
+
+    use super::generate_fence_texture;
+    use crate::assets::Assets;
+
+    #[test]
+    fn fence_shape_follows_connections() {
+        let pack = std::env::temp_dir().join(format!("mc_viewer_fence_{}", std::process::id()));
+        let textures = pack.join("assets/minecraft/textures/block");
+        fs::create_dir_all(&textures).unwrap();
+        fs::write(pack.join("pack.mcmeta"), "{}").unwrap();
+        RgbaImage::from_pixel(16, 16, Rgba([160, 120, 70, 255]))
+            .save(textures.join("oak_planks.png"))
+            .unwrap();
+        let assets = Assets::new(&[pack.to_string_lossy().to_string()]);
+        let fence = Block::from_name("minecraft:oak_fence".into(), None, None, String::new());
+
+        let post = generate_fence_texture(&fence, 0, &assets).unwrap();
+        let west = generate_fence_texture(&fence, 0b0001, &assets).unwrap();
+        let east = generate_fence_texture(&fence, 0b0010, &assets).unwrap();
+        fs::remove_dir_all(&pack).unwrap();
+
+        assert_ne!(west.as_bytes(), east.as_bytes());
+        assert_eq!(post.get_pixel(8, 8), Rgba([160, 120, 70, 255]));
+        assert_eq!(post.get_pixel(8, 12)[3], 0);
+        assert_eq!(west.get_pixel(8, 12)[3], 255);
+        assert_eq!(east.get_pixel(8, 3)[3], 255);
+        assert_eq!(east.get_pixel(8, 12)[3], 0);
+    }
 }
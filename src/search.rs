@@ -0,0 +1,345 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use bevy::{
+    prelude::*,
+    render::camera::Camera2d,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use bevy_egui::{
+    egui::{self, Color32, Rect},
+    EguiContext,
+};
+use futures_lite::future;
+
+use crate::{
+    level::ChunkFormat,
+    markers::short_id,
+    navigation::centre_on,
+    overlays::to_screen,
+    render::analysis::{self, MAX_Y},
+    tiles::{visible_tiles, CHUNK_SIZE},
+    world::WorldView,
+    UIState,
+};
+
+// Cells smaller than this are merged with their neighbours, like the heatmap
+const MIN_CELL_PIXELS: f32 = 4.0;
+const ALPHA: u8 = 180;
+// Listing every hit of a common block would only slow the window down
+const MAX_LISTED: usize = 1000;
+
+// A block that matched the search, by id and position
+type Hit = (String, (i32, i32, i32));
+// Hits in a region and how many chunks could not be read, tagged with the search they belong to
+type RegionResult = (u64, (Vec<Hit>, usize));
+
+pub struct BlockSearch {
+    query: String,
+    min_y: i32,
+    max_y: i32,
+    // Bumped for every search so results of an older one are dropped
+    id: u64,
+    cancel: Arc<AtomicBool>,
+    regions: usize,
+    scanned: usize,
+    unreadable_chunks: usize,
+    hits: Vec<Hit>,
+    // Hits per block id, kept up to date as regions come in
+    counts: BTreeMap<String, usize>,
+    // Hits per chunk for the density overlay
+    density: HashMap<(i32, i32), u32>,
+    errors: Vec<String>,
+    shown: bool,
+}
+
+impl Default for BlockSearch {
+    fn default() -> Self {
+        BlockSearch {
+            query: "diamond_ore, deepslate_diamond_ore, ancient_debris, spawner".to_string(),
+            min_y: -64,
+            max_y: 16,
+            id: 0,
+            cancel: Arc::new(AtomicBool::new(false)),
+            regions: 0,
+            scanned: 0,
+            unreadable_chunks: 0,
+            hits: Vec::new(),
+            counts: BTreeMap::new(),
+            density: HashMap::new(),
+            errors: Vec::new(),
+            shown: true,
+        }
+    }
+}
+
+impl BlockSearch {
+    fn is_running(&self) -> bool {
+        self.scanned < self.regions
+    }
+
+    // Block ids without their namespace, the way simple_anvil reports them
+    fn ids(&self) -> Vec<String> {
+        self.query
+            .split(',')
+            .map(|id| short_id(id.trim()).to_string())
+            .filter(|id| !id.is_empty())
+            .collect()
+    }
+}
+
+// Scans every column of every chunk in a region between two heights, chunks that cannot be read are counted and
+// skipped like the tile renderer does
+fn search_region(
+    world: &WorldView,
+    (region_x, region_z): (i32, i32),
+    ids: &[String],
+    (min_y, max_y): (i32, i32),
+    cancel: &AtomicBool,
+) -> (Vec<Hit>, usize) {
+    let mut hits = Vec::new();
+    let mut unreadable = 0;
+    for chunk_x in region_x * 32..region_x * 32 + 32 {
+        for chunk_z in region_z * 32..region_z * 32 + 32 {
+            if cancel.load(Ordering::Relaxed) {
+                return (hits, unreadable);
+            }
+            let chunk = match world.get_chunk(chunk_x, chunk_z) {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => continue,
+                Err(_) => {
+                    unreadable += 1;
+                    continue;
+                }
+            };
+            // simple_anvil panics on sections it cannot parse, the rest of the region is still worth searching
+            let found = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut found = Vec::new();
+                for y in min_y..=max_y {
+                    for x in 0..16 {
+                        for z in 0..16 {
+                            let block = chunk.get_block(x, y, z);
+                            if ids.contains(&block.id) {
+                                found.push((
+                                    block.id.clone(),
+                                    (chunk_x * 16 + x, y, chunk_z * 16 + z),
+                                ));
+                            }
+                        }
+                    }
+                }
+                found
+            }));
+            match found {
+                Ok(mut found) => hits.append(&mut found),
+                Err(_) => unreadable += 1,
+            }
+        }
+    }
+    (hits, unreadable)
+}
+
+fn start_search(
+    commands: &mut Commands,
+    thread_pool: &AsyncComputeTaskPool,
+    search: &mut BlockSearch,
+    save_path: &str,
+) {
+    search.cancel.store(true, Ordering::Relaxed);
+    search.cancel = Arc::new(AtomicBool::new(false));
+    search.id += 1;
+    search.hits.clear();
+    search.counts.clear();
+    search.density.clear();
+    search.errors.clear();
+    search.scanned = 0;
+    search.unreadable_chunks = 0;
+    let world = Arc::new(WorldView::new(save_path));
+    let regions = match world.regions() {
+        Ok(regions) => regions,
+        Err(e) => {
+            search.regions = 0;
+            search.errors.push(e.to_string());
+            return;
+        }
+    };
+    search.regions = regions.len();
+    let ids = Arc::new(search.ids());
+    let y_range = (
+        search.min_y.min(search.max_y),
+        search.min_y.max(search.max_y),
+    );
+    for region in regions {
+        let (id, world, ids, cancel) =
+            (search.id, world.clone(), ids.clone(), search.cancel.clone());
+        let task: Task<RegionResult> = thread_pool
+            .spawn(async move { (id, search_region(&world, region, &ids, y_range, &cancel)) });
+        commands.spawn().insert(task);
+    }
+}
+
+pub fn handle_search_results(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut Task<RegionResult>)>,
+    mut search: ResMut<BlockSearch>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        if let Some((id, result)) = future::block_on(future::poll_once(&mut *task)) {
+            if id == search.id {
+                search.scanned += 1;
+                let (hits, unreadable) = result;
+                for (id, (x, _, z)) in &hits {
+                    *search.counts.entry(id.clone()).or_default() += 1;
+                    *search
+                        .density
+                        .entry((x.div_euclid(16), z.div_euclid(16)))
+                        .or_default() += 1;
+                }
+                search.hits.extend(hits);
+                search.unreadable_chunks += unreadable;
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub fn block_search_window(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    mut search: ResMut<BlockSearch>,
+    mut ui_state: ResMut<UIState>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+    windows: Res<Windows>,
+    mut cameras: Query<(&mut Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    if ui_state.save_path.is_empty() {
+        return;
+    }
+    // Older worlds start at 0 rather than -64
+    let min_y = match &ui_state.level {
+        Some(Ok(level)) => level.chunk_format(),
+        _ => ChunkFormat::CavesAndCliffs,
+    }
+    .min_y();
+    let y_range = min_y..=MAX_Y - 1;
+    search.min_y = search.min_y.clamp(min_y, MAX_Y - 1);
+    search.max_y = search.max_y.clamp(min_y, MAX_Y - 1);
+    let mut start = false;
+    let mut target = None;
+    let ctx = egui_context.ctx_mut();
+    egui::Window::new("Block Search").show(ctx, |ui| {
+        ui.label("Block ids, separated by commas");
+        ui.text_edit_singleline(&mut search.query);
+        ui.horizontal(|ui| {
+            ui.label("Y from");
+            ui.add(egui::DragValue::new(&mut search.min_y).clamp_range(y_range.clone()));
+            ui.label("to");
+            ui.add(egui::DragValue::new(&mut search.max_y).clamp_range(y_range));
+        });
+        ui.horizontal(|ui| {
+            start = ui.button("Search").clicked();
+            if search.is_running() && ui.button("Cancel").clicked() {
+                search.cancel.store(true, Ordering::Relaxed);
+                // Regions still being scanned stop early and are not waited for
+                search.id += 1;
+                search.regions = search.scanned;
+            }
+            ui.checkbox(&mut search.shown, "Show on map");
+        });
+        if search.is_running() {
+            ui.add(
+                egui::ProgressBar::new(search.scanned as f32 / search.regions as f32)
+                    .text(format!("{}/{} regions", search.scanned, search.regions)),
+            );
+        }
+        for error in &search.errors {
+            ui.colored_label(Color32::RED, error);
+        }
+        if search.unreadable_chunks > 0 {
+            ui.colored_label(
+                Color32::YELLOW,
+                format!("{} chunks could not be read", search.unreadable_chunks),
+            );
+        }
+        for (id, count) in &search.counts {
+            ui.label(format!("{}: {}", id, count));
+        }
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for (id, (x, y, z)) in search.hits.iter().take(MAX_LISTED) {
+                    ui.horizontal(|ui| {
+                        if ui.button("Go").clicked() {
+                            target = Some((*x, *z));
+                        }
+                        ui.label(format!("{} {} {} {}", id, x, y, z));
+                    });
+                }
+                if search.hits.len() > MAX_LISTED {
+                    ui.weak(format!("{} more", search.hits.len() - MAX_LISTED));
+                }
+            });
+    });
+    if start {
+        let save_path = ui_state.save_path.clone();
+        start_search(&mut commands, &thread_pool, &mut search, &save_path);
+    }
+    let (mut camera, projection) = match cameras.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    if let Some(target) = target {
+        centre_on(&mut camera, target);
+        ui_state.viewport_moved = true;
+    }
+    if !search.shown || search.density.is_empty() {
+        return;
+    }
+
+    // Density of hits per chunk, merged into larger cells when zoomed out
+    let window = windows.get_primary().unwrap();
+    let visible = visible_tiles(&camera, projection, window, CHUNK_SIZE);
+    let mut cell = 1;
+    while CHUNK_SIZE * cell as f32 / projection.scale < MIN_CELL_PIXELS {
+        cell *= 2;
+    }
+    let mut cells: HashMap<(i32, i32), u32> = HashMap::new();
+    for (&coords, &count) in &search.density {
+        if visible.contains(coords) {
+            *cells
+                .entry((coords.0.div_euclid(cell), coords.1.div_euclid(cell)))
+                .or_default() += count;
+        }
+    }
+    let max = cells.values().copied().max().unwrap_or(1).max(1) as f32;
+    let painter = ctx.layer_painter(egui::LayerId::background());
+    let blocks = 16.0 * cell as f32;
+    for ((x, z), count) in cells {
+        let min = to_screen(
+            window,
+            &camera,
+            projection,
+            (x as f32 * blocks, z as f32 * blocks),
+        );
+        let max_corner = to_screen(
+            window,
+            &camera,
+            projection,
+            ((x + 1) as f32 * blocks, (z + 1) as f32 * blocks),
+        );
+        // Square root so a single hit next to a rich chunk is still visible
+        let [r, g, b] = analysis::ramp((count as f32 / max).sqrt()).0;
+        painter.rect_filled(
+            Rect::from_min_max(min, max_corner),
+            0.0,
+            Color32::from_rgba_unmultiplied(r, g, b, ALPHA),
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use std::{collections::HashMap, fs::File, path::Path};
+
+use nbt::{Blob, Value};
+
+// Helpers for picking typed values out of NBT compounds, any tag of the wrong type is treated as missing
+
+pub type Compound = HashMap<String, Value>;
+
+// Anything tags can be looked up in by name, compounds and the root of a file
+pub trait Tags {
+    fn tag(&self, name: &str) -> Option<&Value>;
+}
+
+impl Tags for Compound {
+    fn tag(&self, name: &str) -> Option<&Value> {
+        self.get(name)
+    }
+}
+
+impl Tags for Blob {
+    fn tag(&self, name: &str) -> Option<&Value> {
+        self.get(name)
+    }
+}
+
+pub fn read_gzip<P: AsRef<Path>>(path: P) -> nbt::Result<Blob> {
+    let mut file = File::open(path)?;
+    Blob::from_gzip_reader(&mut file)
+}
+
+pub fn compound<'a, T: Tags>(tags: &'a T, name: &str) -> Option<&'a Compound> {
+    match tags.tag(name)? {
+        Value::Compound(value) => Some(value),
+        _ => None,
+    }
+}
+
+pub fn list<'a, T: Tags>(tags: &'a T, name: &str) -> Option<&'a Vec<Value>> {
+    match tags.tag(name)? {
+        Value::List(value) => Some(value),
+        _ => None,
+    }
+}
+
+pub fn string<T: Tags>(tags: &T, name: &str) -> Option<String> {
+    match tags.tag(name)? {
+        Value::String(value) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+pub fn byte<T: Tags>(tags: &T, name: &str) -> Option<i8> {
+    match tags.tag(name)? {
+        Value::Byte(value) => Some(*value),
+        _ => None,
+    }
+}
+
+pub fn int<T: Tags>(tags: &T, name: &str) -> Option<i32> {
+    match tags.tag(name)? {
+        Value::Int(value) => Some(*value),
+        _ => None,
+    }
+}
+
+pub fn long<T: Tags>(tags: &T, name: &str) -> Option<i64> {
+    match tags.tag(name)? {
+        Value::Long(value) => Some(*value),
+        _ => None,
+    }
+}
+
+pub fn byte_array<'a, T: Tags>(tags: &'a T, name: &str) -> Option<&'a Vec<i8>> {
+    match tags.tag(name)? {
+        Value::ByteArray(value) => Some(value),
+        _ => None,
+    }
+}
+
+pub fn int_array<'a, T: Tags>(tags: &'a T, name: &str) -> Option<&'a Vec<i32>> {
+    match tags.tag(name)? {
+        Value::IntArray(value) => Some(value),
+        _ => None,
+    }
+}
+
+// A position stored as a list of three doubles, like an entity's `Pos`
+pub fn position<T: Tags>(tags: &T, name: &str) -> Option<(f64, f64, f64)> {
+    let values = list(tags, name)?
+        .iter()
+        .map(|v| match v {
+            Value::Double(value) => Some(*value),
+            _ => None,
+        })
+        .collect::<Option<Vec<f64>>>()?;
+    match values[..] {
+        [x, y, z] => Some((x, y, z)),
+        _ => None,
+    }
+}
@@ -0,0 +1,324 @@
+use std::{collections::HashMap, path::Path};
+
+use bevy::{prelude::*, render::camera::Camera2d};
+
+use crate::{
+    jobs::{JobManager, Priority, WorkItem},
+    render::REGION_IMAGE_SIZES,
+    RenderContexts, UIState,
+};
+
+// Chunks just outside the window are requested as well so short pans do not show gaps
+const LOAD_MARGIN: i32 = 1;
+// Tiles are only evicted well past the load margin so panning back and forth does not thrash
+const EVICT_MARGIN: i32 = 6;
+// World units covered by a chunk tile
+pub const CHUNK_SIZE: f32 = 256.0;
+pub const REGION_SIZE: f32 = CHUNK_SIZE * 32.0;
+// Past this scale there are too many chunks on screen and stitched region images are shown instead, switching
+// back happens a little closer in so hovering around the threshold does not flip between them
+const REGION_LOD_SCALE: f32 = 6.0;
+const CHUNK_LOD_SCALE: f32 = 4.0;
+
+// Level of detail of the tiles on the map
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Lod {
+    #[default]
+    Chunk,
+    // Region images of one of the sizes they are written at
+    Region(u32),
+}
+
+impl Lod {
+    fn for_scale(self, scale: f32) -> Self {
+        match self {
+            Lod::Chunk if scale > REGION_LOD_SCALE => Lod::Region(region_image_size(scale)),
+            Lod::Region(_) if scale < CHUNK_LOD_SCALE => Lod::Chunk,
+            Lod::Region(_) => Lod::Region(region_image_size(scale)),
+            lod => lod,
+        }
+    }
+
+    fn tile_size(self) -> f32 {
+        match self {
+            Lod::Chunk => CHUNK_SIZE,
+            Lod::Region(_) => REGION_SIZE,
+        }
+    }
+}
+
+// The smallest region image that still has a pixel for every pixel the region covers on screen
+fn region_image_size(scale: f32) -> u32 {
+    let pixels = REGION_SIZE / scale;
+    REGION_IMAGE_SIZES
+        .iter()
+        .rev()
+        .copied()
+        .find(|size| *size as f32 >= pixels)
+        .unwrap_or(REGION_IMAGE_SIZES[0])
+}
+
+// An inclusive rectangle of chunk or region coordinates
+#[derive(Clone, Copy)]
+pub struct TileRange {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+impl TileRange {
+    pub fn expand(&self, margin: i32) -> Self {
+        TileRange {
+            min: (self.min.0 - margin, self.min.1 - margin),
+            max: (self.max.0 + margin, self.max.1 + margin),
+        }
+    }
+
+    pub fn contains(&self, (x, z): (i32, i32)) -> bool {
+        x >= self.min.0 && x <= self.max.0 && z >= self.min.1 && z <= self.max.1
+    }
+
+    // Every chunk in the range, closest to the centre first so the middle of the screen fills in first
+    pub fn by_distance(&self) -> Vec<(i32, i32)> {
+        let centre = (
+            (self.min.0 + self.max.0) as f32 / 2.0,
+            (self.min.1 + self.max.1) as f32 / 2.0,
+        );
+        let mut chunks = Vec::new();
+        for x in self.min.0..=self.max.0 {
+            for z in self.min.1..=self.max.1 {
+                chunks.push((x, z));
+            }
+        }
+        chunks.sort_by(|a, b| {
+            let distance =
+                |c: &(i32, i32)| (c.0 as f32 - centre.0).powi(2) + (c.1 as f32 - centre.1).powi(2);
+            distance(a).partial_cmp(&distance(b)).unwrap()
+        });
+        chunks
+    }
+}
+
+// The tiles of a size the camera can currently see. World z grows downwards on screen so it is the negated
+// y axis.
+pub fn visible_tiles(
+    transform: &Transform,
+    projection: &OrthographicProjection,
+    window: &Window,
+    tile_size: f32,
+) -> TileRange {
+    let half_width = window.width() / 2.0 * projection.scale;
+    let half_height = window.height() / 2.0 * projection.scale;
+    let (x, z) = (transform.translation.x, -transform.translation.y);
+    TileRange {
+        min: (
+            ((x - half_width) / tile_size).floor() as i32,
+            ((z - half_height) / tile_size).floor() as i32,
+        ),
+        max: (
+            ((x + half_width) / tile_size).floor() as i32,
+            ((z + half_height) / tile_size).floor() as i32,
+        ),
+    }
+}
+
+pub fn spawn_region_sprite(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    path: &str,
+    (region_x, region_z): (i32, i32),
+) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: asset_server.load(&format!(
+                "{}\\saves{}",
+                std::env::current_dir()
+                    .unwrap()
+                    .to_path_buf()
+                    .to_str()
+                    .unwrap(),
+                &path[5..]
+            )),
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(REGION_SIZE)),
+                ..default()
+            },
+            transform: Transform::from_xyz(
+                region_x as f32 * REGION_SIZE + REGION_SIZE / 2.0,
+                region_z as f32 * -REGION_SIZE - REGION_SIZE / 2.0,
+                1.0,
+            ),
+            ..default()
+        })
+        .id()
+}
+
+// Tracks which tiles are on the map so the viewport only ever asks for the ones it is missing
+#[derive(Default)]
+pub struct TileManager {
+    lod: Lod,
+    // `None` for tiles that have nothing to show, so they are not requested again
+    loaded: HashMap<(Lod, (i32, i32)), Option<Entity>>,
+    // Tiles waiting on a job, by job id
+    pending: HashMap<(Lod, (i32, i32)), u64>,
+    // The job the viewport's tiles of each level of detail are rendered by, given new work whenever the camera moves
+    viewport_jobs: HashMap<Lod, u64>,
+}
+
+impl TileManager {
+    fn is_known(&self, tile: &(Lod, (i32, i32))) -> bool {
+        self.loaded.contains_key(tile) || self.pending.contains_key(tile)
+    }
+
+    // Registers the result for a tile, returns the sprite it replaces
+    pub fn insert(
+        &mut self,
+        lod: Lod,
+        coords: (i32, i32),
+        entity: Option<Entity>,
+    ) -> Option<Entity> {
+        self.pending.remove(&(lod, coords));
+        self.loaded.insert((lod, coords), entity).flatten()
+    }
+
+    // Despawns every tile of another level of detail or outside of `keep`
+    fn evict(&mut self, commands: &mut Commands, keep: &TileRange) {
+        let lod = self.lod;
+        self.loaded.retain(|(tile_lod, coords), entity| {
+            if *tile_lod == lod && keep.contains(*coords) {
+                return true;
+            }
+            if let Some(entity) = entity {
+                commands.entity(*entity).despawn();
+            }
+            false
+        });
+    }
+
+    fn forget_job(&mut self, job: u64) {
+        self.pending.retain(|_, id| *id != job);
+    }
+
+    // Forgets every tile, for when the sprites were despawned elsewhere
+    pub fn clear(&mut self) {
+        self.loaded.clear();
+        self.pending.clear();
+        self.viewport_jobs.clear();
+    }
+}
+
+pub fn stream_tiles(
+    mut commands: Commands,
+    mut ui_state: ResMut<UIState>,
+    mut tiles: ResMut<TileManager>,
+    mut jobs: ResMut<JobManager>,
+    mut contexts: ResMut<RenderContexts>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+    windows: Res<Windows>,
+) {
+    if !ui_state.rendering_viewport || !ui_state.viewport_moved || ui_state.save_path.is_empty() {
+        return;
+    }
+    ui_state.viewport_moved = false;
+    let (transform, projection) = match cameras.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    tiles.lod = tiles.lod.for_scale(projection.scale);
+    let lod = tiles.lod;
+    let visible = visible_tiles(
+        transform,
+        projection,
+        windows.get_primary().unwrap(),
+        lod.tile_size(),
+    );
+
+    tiles.evict(&mut commands, &visible.expand(EVICT_MARGIN));
+    // Whatever was visible before the move is no longer worth rendering first, queued work is taken back and
+    // requested again below if it is still visible
+    for (tile_lod, id) in tiles.viewport_jobs.clone() {
+        if jobs.is_open(id) {
+            for coords in jobs.take_queue(id).iter().filter_map(WorkItem::coords) {
+                tiles.pending.remove(&(tile_lod, coords));
+            }
+        } else {
+            // Cancelled from the jobs window or by a mode switch
+            tiles.forget_job(id);
+            tiles.viewport_jobs.remove(&tile_lod);
+        }
+    }
+
+    let missing = visible
+        .expand(LOAD_MARGIN)
+        .by_distance()
+        .into_iter()
+        .filter(|coords| !tiles.is_known(&(lod, *coords)))
+        .collect::<Vec<(i32, i32)>>();
+    let (name, ctx, items) = match lod {
+        Lod::Chunk => {
+            // Panning keeps the decoded regions of the running viewport job
+            let running = tiles
+                .viewport_jobs
+                .get(&lod)
+                .and_then(|id| jobs.get(*id))
+                .and_then(|job| job.ctx.clone());
+            let ctx = match contexts.reuse(&mut ui_state, running) {
+                Ok(ctx) => ctx,
+                // Nothing in this world can be rendered, stop trying on every move
+                Err(e) => {
+                    ui_state.failed_chunks.push(e.to_string());
+                    ui_state.rendering_viewport = false;
+                    return;
+                }
+            };
+            (
+                "Viewport",
+                Some(ctx),
+                missing
+                    .iter()
+                    .map(|chunk| (*chunk, WorkItem::Chunk(*chunk)))
+                    .collect::<Vec<((i32, i32), WorkItem)>>(),
+            )
+        }
+        // Regions are only shown from chunk tiles that already exist, rendering every chunk of every visible
+        // region whenever the camera moves far out would never finish
+        Lod::Region(size) => {
+            let mut items = Vec::new();
+            for (region_x, region_z) in &missing {
+                let folder = Path::new("saves")
+                    .join(ui_state.tile_set())
+                    .join(format!("r.{}.{}", region_x, region_z));
+                // The image is stitched again in the background when its chunk tiles changed
+                if folder.exists() {
+                    items.push(((*region_x, *region_z), WorkItem::Region(folder, size)));
+                } else {
+                    tiles.insert(lod, (*region_x, *region_z), None);
+                }
+            }
+            ("Viewport regions", None, items)
+        }
+    };
+    let (queued, items): (Vec<(i32, i32)>, Vec<WorkItem>) = items.into_iter().unzip();
+    let id = match tiles.viewport_jobs.get(&lod).copied() {
+        Some(id) => {
+            jobs.requeue(id, ctx, items);
+            id
+        }
+        None if items.is_empty() => return,
+        None => {
+            let id = jobs.submit(name.to_string(), Priority::Viewport, ctx, items);
+            tiles.viewport_jobs.insert(lod, id);
+            id
+        }
+    };
+    for coords in queued {
+        tiles.pending.insert((lod, coords), id);
+    }
+    // The other level of detail has nothing queued any more, let its job finish
+    for (_, id) in tiles
+        .viewport_jobs
+        .iter()
+        .filter(|(tile_lod, _)| **tile_lod != lod)
+    {
+        jobs.requeue(*id, None, Vec::new());
+    }
+}
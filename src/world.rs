@@ -0,0 +1,165 @@
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use lru::LruCache;
+use simple_anvil::{block::Block, chunk::Chunk, region::Region};
+
+use crate::render::error::RenderError;
+
+const REGION_CACHE_SIZE: usize = 16;
+const CHUNK_CACHE_SIZE: usize = 512;
+
+// `None` remembers that a region or chunk does not exist so it is not looked for again
+type RegionCache = LruCache<(i32, i32), Option<Arc<Region>>>;
+type ChunkCache = LruCache<(i32, i32), Option<Arc<Chunk>>>;
+
+// Shared read access to a world's region files. Everything is addressed in world coordinates so callers never
+// have to care which region file or chunk a block ends up in, decoded regions and chunks are kept in an lru
+// so neighbour lookups across chunk borders are cheap.
+pub struct WorldView {
+    region_dir: PathBuf,
+    regions: Mutex<RegionCache>,
+    chunks: Mutex<ChunkCache>,
+}
+
+impl WorldView {
+    pub fn new<P: AsRef<Path>>(save_path: P) -> Self {
+        WorldView {
+            region_dir: save_path.as_ref().join("region"),
+            regions: Mutex::new(LruCache::new(REGION_CACHE_SIZE)),
+            chunks: Mutex::new(LruCache::new(CHUNK_CACHE_SIZE)),
+        }
+    }
+
+    // A view of the same world with nothing cached, so regions and chunks changed on disk are read again
+    pub fn reopen(&self) -> Self {
+        WorldView {
+            region_dir: self.region_dir.clone(),
+            regions: Mutex::new(LruCache::new(REGION_CACHE_SIZE)),
+            chunks: Mutex::new(LruCache::new(CHUNK_CACHE_SIZE)),
+        }
+    }
+
+    pub fn region_path(&self, region_x: i32, region_z: i32) -> PathBuf {
+        self.region_dir
+            .join(format!("r.{}.{}.mca", region_x, region_z))
+    }
+
+    // Coordinates of every region file in the world
+    pub fn regions(&self) -> Result<Vec<(i32, i32)>, RenderError> {
+        match fs::read_dir(&self.region_dir) {
+            Ok(dir) => Ok(dir
+                .filter_map(|f| f.ok())
+                .filter_map(|f| region_coords_from_name(f.file_name().to_str()?))
+                .collect()),
+            Err(_) => Err(RenderError::MissingRegionDirectory(self.region_dir.clone())),
+        }
+    }
+
+    pub fn get_region(
+        &self,
+        region_x: i32,
+        region_z: i32,
+    ) -> Result<Option<Arc<Region>>, RenderError> {
+        if let Some(region) = self
+            .regions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&(region_x, region_z))
+        {
+            return Ok(region.clone());
+        }
+        // Load outside of the lock so other workers are not stuck waiting on disk
+        let path = self.region_path(region_x, region_z);
+        let region = if path.exists() {
+            let file = path.to_str().unwrap().to_string();
+            let region = panic::catch_unwind(|| Region::from_file(file)).map_err(|e| {
+                RenderError::CorruptRegion((region_x, region_z), RenderError::panic_message(e))
+            })?;
+            Some(Arc::new(region))
+        } else {
+            None
+        };
+        self.regions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .put((region_x, region_z), region.clone());
+        Ok(region)
+    }
+
+    pub fn get_chunk(&self, chunk_x: i32, chunk_z: i32) -> Result<Option<Arc<Chunk>>, RenderError> {
+        if let Some(chunk) = self
+            .chunks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&(chunk_x, chunk_z))
+        {
+            return Ok(chunk.clone());
+        }
+        let chunk = match self.get_region(chunk_x.div_euclid(32), chunk_z.div_euclid(32))? {
+            Some(region) => panic::catch_unwind(AssertUnwindSafe(|| {
+                region.get_chunk(chunk_x.rem_euclid(32) as u32, chunk_z.rem_euclid(32) as u32)
+            }))
+            .map_err(|e| {
+                RenderError::CorruptChunk((chunk_x, chunk_z), RenderError::panic_message(e))
+            })?
+            .map(Arc::new),
+            None => None,
+        };
+        self.chunks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .put((chunk_x, chunk_z), chunk.clone());
+        Ok(chunk)
+    }
+
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Result<Option<Block>, RenderError> {
+        let chunk = match self.get_chunk(x.div_euclid(16), z.div_euclid(16))? {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            chunk.get_block(x.rem_euclid(16), y, z.rem_euclid(16))
+        }))
+        .map(Some)
+        .map_err(|e| {
+            RenderError::CorruptChunk(
+                (x.div_euclid(16), z.div_euclid(16)),
+                RenderError::panic_message(e),
+            )
+        })
+    }
+}
+
+// Parses `r.{x}.{z}.mca` or `r.{x}.{z}` into region coordinates
+pub fn region_coords_from_name(name: &str) -> Option<(i32, i32)> {
+    let parts = name.split(".").collect::<Vec<&str>>();
+    if parts.len() < 3 || parts[0] != "r" {
+        return None;
+    }
+    Some((parts[1].parse().ok()?, parts[2].parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::region_coords_from_name;
+
+    #[test]
+    fn parses_region_names() {
+        assert_eq!(region_coords_from_name("r.0.0.mca"), Some((0, 0)));
+        assert_eq!(region_coords_from_name("r.-3.12.mca"), Some((-3, 12)));
+        assert_eq!(region_coords_from_name("r.5.-1"), Some((5, -1)));
+    }
+
+    #[test]
+    fn rejects_other_names() {
+        assert_eq!(region_coords_from_name("r.0.mca"), None);
+        assert_eq!(region_coords_from_name("c.0.0.mcc"), None);
+        assert_eq!(region_coords_from_name("r.a.0.mca"), None);
+        assert_eq!(region_coords_from_name("level.dat"), None);
+    }
+}